@@ -0,0 +1,363 @@
+//! Which consoles users may connect to.
+//!
+//! Rules are passed with `--allow` and look like:
+//!
+//! - `10.0.0.0/8`, `127.0.0.1` or `[fd00::/8]`: addresses in a network.
+//! - `console.example.com` or `*.example.com`: host names.
+//! - `unix:///run/console.sock` or `unix:///run/consoles/*`: a Unix socket or every socket in a
+//!   directory.
+//!
+//! Host rules take an optional port or port range, such as `10.0.0.0/8:6669` or
+//! `*.example.com:6669-6679`. Host names are resolved and must only resolve to allowed addresses
+//! unless a host name rule allows them. This is checked again on every connection, including
+//! reconnects, and the connection goes to the checked addresses.
+//!
+//! Consoles from the config file and `--metrics-console` are always allowed.
+
+use crate::console_addr::ConsoleAddr;
+use anyhow::Context as _;
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The consoles users may connect to. Everything is allowed if there are no rules.
+#[derive(Debug, Default, Clone)]
+pub struct Allowlist {
+    rules: Arc<Vec<AllowRule>>,
+    trusted: Arc<HashSet<ConsoleAddr>>,
+}
+
+impl Allowlist {
+    pub fn new(rules: Vec<AllowRule>) -> Self {
+        Self {
+            rules: Arc::new(rules),
+            trusted: Default::default(),
+        }
+    }
+
+    /// Allow these consoles regardless of the rules, because they were configured by whoever
+    /// runs the server.
+    pub fn trust(mut self, addrs: impl IntoIterator<Item = ConsoleAddr>) -> Self {
+        self.trusted = Arc::new(addrs.into_iter().collect());
+        self
+    }
+
+    /// Fail unless `addr` is allowed, logging the refusal.
+    pub async fn check(&self, addr: &ConsoleAddr) -> anyhow::Result<()> {
+        if self.rules.is_empty() || self.trusted.contains(addr) {
+            return Ok(());
+        }
+
+        match addr {
+            ConsoleAddr::Unix(path) => {
+                if self.rules.iter().any(|rule| rule.allows_unix(path)) {
+                    Ok(())
+                } else {
+                    Err(refuse(addr))
+                }
+            }
+            ConsoleAddr::Tcp { .. } => self.resolve(addr).await.map(drop),
+        }
+    }
+
+    /// The socket addresses to connect to for `addr`, failing unless every one of them is allowed.
+    ///
+    /// Connections must go to these rather than resolving the host again, since it could resolve
+    /// to something else by then.
+    pub async fn resolve(&self, addr: &ConsoleAddr) -> anyhow::Result<Vec<SocketAddr>> {
+        let (host, port) = match addr {
+            ConsoleAddr::Tcp { host, port } => (host, *port),
+            ConsoleAddr::Unix(_) => anyhow::bail!("Can't resolve Unix socket {}", addr),
+        };
+
+        let addrs = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .with_context(|| format!("Failed to resolve {}", addr))?
+            .collect::<Vec<_>>();
+        anyhow::ensure!(!addrs.is_empty(), "{} doesn't resolve to any address", addr);
+
+        let unrestricted = self.rules.is_empty()
+            || self.trusted.contains(addr)
+            || (host.parse::<IpAddr>().is_err()
+                && self.rules.iter().any(|rule| rule.allows_name(host, port)));
+
+        // the name could point anywhere, so check everything it resolves to
+        if !unrestricted && !addrs.iter().all(|addr| self.allows_ip(addr.ip(), port)) {
+            return Err(refuse(addr));
+        }
+
+        Ok(addrs)
+    }
+
+    fn allows_ip(&self, ip: IpAddr, port: u16) -> bool {
+        self.rules.iter().any(|rule| rule.allows_ip(ip, port))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowRule {
+    Network {
+        addr: IpAddr,
+        prefix_len: u8,
+        ports: Option<RangeInclusive<u16>>,
+    },
+    /// A lowercase host name, which matches every subdomain if it starts with `*.`.
+    Name {
+        name: String,
+        ports: Option<RangeInclusive<u16>>,
+    },
+    Unix {
+        path: PathBuf,
+        /// Whether `path` is a directory, allowing every socket in it.
+        dir: bool,
+    },
+}
+
+impl AllowRule {
+    fn allows_ip(&self, ip: IpAddr, port: u16) -> bool {
+        match self {
+            AllowRule::Network {
+                addr,
+                prefix_len,
+                ports,
+            } => in_network(ip, *addr, *prefix_len) && in_ports(ports, port),
+            _ => false,
+        }
+    }
+
+    fn allows_name(&self, host: &str, port: u16) -> bool {
+        match self {
+            AllowRule::Name { name, ports } => {
+                let host = host.trim_end_matches('.').to_lowercase();
+                let matches = match name.strip_prefix("*.") {
+                    Some(domain) => host
+                        .strip_suffix(domain)
+                        .map_or(false, |sub| sub.len() > 1 && sub.ends_with('.')),
+                    None => host == *name,
+                };
+                matches && in_ports(ports, port)
+            }
+            _ => false,
+        }
+    }
+
+    fn allows_unix(&self, socket: &Path) -> bool {
+        // `..` could be used to escape an allowed directory
+        if socket
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return false;
+        }
+
+        match self {
+            AllowRule::Unix { path, dir: false } => socket == path,
+            AllowRule::Unix { path, dir: true } => socket.parent() == Some(path.as_path()),
+            _ => false,
+        }
+    }
+}
+
+fn refuse(addr: &ConsoleAddr) -> anyhow::Error {
+    tracing::warn!(%addr, "audit: refused to connect to console that isn't allowed");
+    anyhow::anyhow!("Connecting to {} is not allowed", addr)
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix_len: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+fn in_ports(ports: &Option<RangeInclusive<u16>>, port: u16) -> bool {
+    ports.as_ref().map_or(true, |ports| ports.contains(&port))
+}
+
+impl FromStr for AllowRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix://") {
+            let (path, dir) = match path.strip_suffix("/*") {
+                Some(dir) => (dir, true),
+                None => (path, false),
+            };
+            anyhow::ensure!(
+                path.starts_with('/'),
+                "Unix socket paths must be absolute, got `{}`",
+                s
+            );
+            return Ok(AllowRule::Unix {
+                path: path.into(),
+                dir,
+            });
+        }
+
+        let (host, ports) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest
+                .split_once(']')
+                .with_context(|| format!("Missing `]` in `{}`", s))?;
+            match rest {
+                "" => (host, None),
+                _ => {
+                    let ports = rest
+                        .strip_prefix(':')
+                        .with_context(|| format!("Expected a port after `]` in `{}`", s))?;
+                    (host, Some(ports))
+                }
+            }
+        } else if s.matches(':').count() > 1 {
+            // an IPv6 address without brackets can't have a port
+            (s, None)
+        } else {
+            match s.split_once(':') {
+                Some((host, ports)) => (host, Some(ports)),
+                None => (s, None),
+            }
+        };
+
+        let ports = ports.map(|ports| parse_ports(ports, s)).transpose()?;
+
+        if let Some((addr, prefix_len)) = host.split_once('/') {
+            let addr = addr
+                .parse::<IpAddr>()
+                .with_context(|| format!("Invalid network address in `{}`", s))?;
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix_len = prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max)
+                .with_context(|| format!("Invalid prefix length in `{}`", s))?;
+            return Ok(AllowRule::Network {
+                addr,
+                prefix_len,
+                ports,
+            });
+        }
+
+        if let Ok(addr) = host.parse::<IpAddr>() {
+            let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+            return Ok(AllowRule::Network {
+                addr,
+                prefix_len,
+                ports,
+            });
+        }
+
+        let name = host.trim_end_matches('.').to_lowercase();
+        let domain = name.strip_prefix("*.").unwrap_or(&name);
+        anyhow::ensure!(
+            !domain.is_empty() && !domain.contains(|c| c == '*' || c == '/'),
+            "Invalid host name in `{}`",
+            s
+        );
+        Ok(AllowRule::Name { name, ports })
+    }
+}
+
+fn parse_ports(ports: &str, rule: &str) -> anyhow::Result<RangeInclusive<u16>> {
+    let invalid = || format!("Invalid port range in `{}`", rule);
+    let (start, end) = ports.split_once('-').unwrap_or((ports, ports));
+    let start = start.parse::<u16>().with_context(invalid)?;
+    let end = end.parse::<u16>().with_context(invalid)?;
+    anyhow::ensure!(start <= end, invalid());
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(rules: &[&str]) -> Allowlist {
+        Allowlist::new(rules.iter().map(|rule| rule.parse().unwrap()).collect())
+    }
+
+    async fn allows(allowlist: &Allowlist, addr: &str) -> bool {
+        allowlist.check(&addr.parse().unwrap()).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn empty_allows_everything() {
+        assert!(allows(&Allowlist::default(), "169.254.169.254:80").await);
+    }
+
+    #[tokio::test]
+    async fn networks_and_ports() {
+        let allowlist = allowlist(&["10.0.0.0/8:6669-6679", "[fd00::/8]", "127.0.0.1"]);
+
+        assert!(allows(&allowlist, "10.1.2.3:6669").await);
+        assert!(allows(&allowlist, "10.1.2.3:6679").await);
+        assert!(!allows(&allowlist, "10.1.2.3:22").await);
+        assert!(!allows(&allowlist, "11.1.2.3:6669").await);
+        assert!(allows(&allowlist, "[fd12::1]:80").await);
+        assert!(!allows(&allowlist, "[fe80::1]:6669").await);
+        assert!(allows(&allowlist, "127.0.0.1:1").await);
+        assert!(!allows(&allowlist, "127.0.0.2:1").await);
+    }
+
+    #[tokio::test]
+    async fn host_names() {
+        let allowlist = allowlist(&["console.example.com:6669", "*.internal"]);
+
+        assert!(allows(&allowlist, "Console.Example.com:6669").await);
+        assert!(!allows(&allowlist, "console.example.com:6670").await);
+        assert!(allows(&allowlist, "worker.internal:6669").await);
+        assert!(!allows(&allowlist, "evilinternal:6669").await);
+    }
+
+    #[tokio::test]
+    async fn resolved_host_names() {
+        let allowlist = allowlist(&["127.0.0.0/8", "::1"]);
+
+        assert!(allows(&allowlist, "localhost:6669").await);
+    }
+
+    #[tokio::test]
+    async fn trusted() {
+        let allowlist = allowlist(&["10.0.0.0/8"]).trust(vec!["192.168.1.1:6669".parse().unwrap()]);
+
+        assert!(allows(&allowlist, "192.168.1.1:6669").await);
+        assert!(!allows(&allowlist, "192.168.1.1:6670").await);
+    }
+
+    #[tokio::test]
+    async fn unix_sockets() {
+        let allowlist = allowlist(&["unix:///run/console.sock", "unix:///run/consoles/*"]);
+
+        assert!(allows(&allowlist, "unix:///run/console.sock").await);
+        assert!(allows(&allowlist, "unix:///run/consoles/a.sock").await);
+        assert!(!allows(&allowlist, "unix:///run/consoles/../secret.sock").await);
+        assert!(!allows(&allowlist, "unix:///run/other.sock").await);
+        assert!(!allows(&allowlist, "127.0.0.1:6669").await);
+    }
+
+    #[test]
+    fn invalid_rules() {
+        for rule in [
+            "10.0.0.0/33",
+            "10.0.0.1:70000",
+            "host:6670-6669",
+            "*",
+            "unix://relative",
+        ] {
+            assert!(rule.parse::<AllowRule>().is_err(), "{}", rule);
+        }
+    }
+}
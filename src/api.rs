@@ -0,0 +1,526 @@
+//! JSON API for scripting against live console state.
+//!
+//! The list endpoints accept these query parameters:
+//!
+//! - `filter`: the same syntax as the filter box in the UI, such as `state:idle polls>1000`.
+//! - `sort`: a filter key such as `polls`. Prefix with `-` to sort descending.
+//! - `limit`: the maximum number of entries to return.
+//!
+//! Consoles are identified like in the UI, such as `/api/consoles/tcp/127.0.0.1/6669/tasks` or
+//! `/api/consoles/unix/%2Ftmp%2Fconsole.sock/tasks`. `/events` is a server-sent events stream of
+//! changes to the state.
+
+use crate::{
+    allowlist::Allowlist,
+    console_addr::ConsoleAddr,
+    lints::Warning,
+    routes::EntityPath,
+    views::filter::{Filter, Filterable},
+    watch_stream::{
+        ConsoleState, ConsoleSubscriptions, MetaId, Metadata, Resource, ResourceId, Task, TaskId,
+        TaskStats,
+    },
+};
+use axum::{
+    async_trait,
+    extract::{Extension, FromRequest, Path, Query, RequestParts},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{cmp::Reverse, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+pub fn routes() -> Router {
+    let pages = [
+        ("/tasks", get(tasks)),
+        ("/tasks/:id", get(task)),
+        ("/resources", get(resources)),
+        ("/resources/:id", get(resource)),
+        ("/events", get(events)),
+    ];
+
+    let mut router = Router::new();
+    for (page, method_router) in pages {
+        for pattern in ConsoleAddr::route_patterns("/api/consoles", page) {
+            router = router.route(&pattern, method_router.clone());
+        }
+    }
+    router
+}
+
+async fn tasks(
+    AllowedConsole(addr): AllowedConsole,
+    Query(query): Query<ListQuery>,
+    Extension(subscriptions): Extension<ConsoleSubscriptions>,
+) -> Result<impl IntoResponse, ApiError> {
+    let state = console_state(&subscriptions, addr).await?;
+
+    let tasks = query.apply(state.tasks.values().map(|task| &**task), &state);
+    let tasks = tasks
+        .into_iter()
+        .map(|task| TaskJson::new(task, &state))
+        .collect::<Vec<_>>();
+
+    Ok(Json(tasks))
+}
+
+async fn task(
+    AllowedConsole(addr): AllowedConsole,
+    Path(EntityPath { id }): Path<EntityPath>,
+    Extension(subscriptions): Extension<ConsoleSubscriptions>,
+) -> Result<impl IntoResponse, ApiError> {
+    let state = console_state(&subscriptions, addr).await?;
+
+    let task = state
+        .tasks
+        .get(&TaskId(id))
+        .ok_or_else(|| ApiError::not_found(format!("Task {} not found", id)))?;
+
+    Ok(Json(TaskJson::new(task, &state)))
+}
+
+async fn resources(
+    AllowedConsole(addr): AllowedConsole,
+    Query(query): Query<ListQuery>,
+    Extension(subscriptions): Extension<ConsoleSubscriptions>,
+) -> Result<impl IntoResponse, ApiError> {
+    let state = console_state(&subscriptions, addr).await?;
+
+    let resources = query.apply(state.resources.values().map(|resource| &**resource), &state);
+    let resources = resources
+        .into_iter()
+        .map(|resource| WithMetadata::new(resource, resource.metadata_id, &state))
+        .collect::<Vec<_>>();
+
+    Ok(Json(resources))
+}
+
+async fn resource(
+    AllowedConsole(addr): AllowedConsole,
+    Path(EntityPath { id }): Path<EntityPath>,
+    Extension(subscriptions): Extension<ConsoleSubscriptions>,
+) -> Result<impl IntoResponse, ApiError> {
+    let state = console_state(&subscriptions, addr).await?;
+
+    let resource = state
+        .resources
+        .get(&ResourceId(id))
+        .ok_or_else(|| ApiError::not_found(format!("Resource {} not found", id)))?;
+
+    Ok(Json(WithMetadata::new(
+        &**resource,
+        resource.metadata_id,
+        &state,
+    )))
+}
+
+async fn events(
+    AllowedConsole(addr): AllowedConsole,
+    Extension(subscriptions): Extension<ConsoleSubscriptions>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut watch = subscriptions
+        .subscribe(addr)
+        .await
+        .map_err(ApiError::bad_gateway)?;
+    watch.connected().await.map_err(ApiError::bad_gateway)?;
+
+    let (tx, rx) = mpsc::channel(64);
+
+    tokio::spawn(async move {
+        let mut prev = watch.borrow().clone();
+
+        while watch.changed().await.is_ok() {
+            let state = watch.borrow().clone();
+
+            for event in StateEvent::diff(&prev, &state) {
+                let event = Event::default().json_data(&event);
+                if tx.send(event).await.is_err() {
+                    // the client went away
+                    return;
+                }
+            }
+
+            prev = state;
+        }
+    });
+
+    Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
+/// A change to the state of a console.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StateEvent {
+    TaskSpawned { task: Task },
+    TaskCompleted { id: TaskId },
+    TaskStatsChanged { id: TaskId, stats: TaskStats },
+    ResourceCreated { resource: Resource },
+    ResourceDropped { id: ResourceId },
+}
+
+impl StateEvent {
+    fn diff(prev: &ConsoleState, state: &ConsoleState) -> Vec<StateEvent> {
+        let mut events = Vec::new();
+
+        for (id, task) in &state.tasks {
+            let prev_task = match prev.tasks.get(id) {
+                Some(prev_task) if Arc::ptr_eq(prev_task, task) => continue,
+                Some(prev_task) => prev_task,
+                None => {
+                    events.push(StateEvent::TaskSpawned {
+                        task: Task::clone(task),
+                    });
+                    // it might have completed before we got to see it
+                    if task.is_completed() {
+                        events.push(StateEvent::TaskCompleted { id: *id });
+                    }
+                    continue;
+                }
+            };
+
+            if task.stats != prev_task.stats {
+                if let Some(stats) = &task.stats {
+                    events.push(StateEvent::TaskStatsChanged {
+                        id: *id,
+                        stats: stats.clone(),
+                    });
+                }
+            }
+
+            if task.is_completed() && !prev_task.is_completed() {
+                events.push(StateEvent::TaskCompleted { id: *id });
+            }
+        }
+
+        // tasks that went away without us seeing them complete
+        for (id, prev_task) in &prev.tasks {
+            if !state.tasks.contains_key(id) && !prev_task.is_completed() {
+                events.push(StateEvent::TaskCompleted { id: *id });
+            }
+        }
+
+        for (id, resource) in &state.resources {
+            match prev.resources.get(id) {
+                Some(prev_resource) => {
+                    if is_dropped(resource) && !is_dropped(prev_resource) {
+                        events.push(StateEvent::ResourceDropped { id: *id });
+                    }
+                }
+                None => events.push(StateEvent::ResourceCreated {
+                    resource: Resource::clone(resource),
+                }),
+            }
+        }
+
+        // resources that went away without us seeing them being dropped
+        for (id, prev_resource) in &prev.resources {
+            if !state.resources.contains_key(id) && !is_dropped(prev_resource) {
+                events.push(StateEvent::ResourceDropped { id: *id });
+            }
+        }
+
+        events
+    }
+}
+
+fn is_dropped(resource: &Resource) -> bool {
+    resource
+        .stats
+        .as_ref()
+        .map_or(false, |stats| stats.dropped_at.is_some())
+}
+
+/// A console from the path that the [`Allowlist`] allows connecting to.
+struct AllowedConsole(ConsoleAddr);
+
+#[async_trait]
+impl<B> FromRequest<B> for AllowedConsole
+where
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let addr = ConsoleAddr::from_request(req).await?;
+        let Extension(allowlist) = Extension::<Allowlist>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        match allowlist.check(&addr).await {
+            Ok(()) => Ok(Self(addr)),
+            Err(err) => Err(ApiError::forbidden(err).into_response()),
+        }
+    }
+}
+
+/// Get the current state of a console, connecting to it if necessary.
+async fn console_state(
+    subscriptions: &ConsoleSubscriptions,
+    addr: ConsoleAddr,
+) -> Result<ConsoleState, ApiError> {
+    let mut watch = subscriptions
+        .subscribe(addr)
+        .await
+        .map_err(ApiError::bad_gateway)?;
+    watch.connected().await.map_err(ApiError::bad_gateway)?;
+
+    let state = watch.borrow().clone();
+    Ok(state)
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    filter: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+}
+
+impl ListQuery {
+    /// Filter, sort and limit `entities` from `state`.
+    fn apply<'a, T, I>(&self, entities: I, state: &ConsoleState) -> Vec<&'a T>
+    where
+        I: Iterator<Item = &'a T>,
+        T: Filterable,
+    {
+        let filter = Filter::parse(self.filter.as_deref().unwrap_or_default());
+        let mut entities = entities
+            .filter(|entity| filter.matches_entity(*entity, state))
+            .collect::<Vec<_>>();
+
+        if let Some(sort) = &self.sort {
+            let (key, descending) = match sort.strip_prefix('-') {
+                Some(key) => (key, true),
+                None => (sort.as_str(), false),
+            };
+
+            // the sort is stable so entities with equal keys stay ordered by id
+            if descending {
+                entities.sort_by_cached_key(|entity| Reverse(entity.filter_value(key, state)));
+            } else {
+                entities.sort_by_cached_key(|entity| entity.filter_value(key, state));
+            }
+        }
+
+        if let Some(limit) = self.limit {
+            entities.truncate(limit);
+        }
+
+        entities
+    }
+}
+
+#[derive(Serialize)]
+struct WithMetadata<'a, T> {
+    #[serde(flatten)]
+    entity: &'a T,
+    metadata: Option<&'a Metadata>,
+}
+
+impl<'a, T> WithMetadata<'a, T> {
+    fn new(entity: &'a T, metadata_id: MetaId, state: &'a ConsoleState) -> Self {
+        Self {
+            entity,
+            metadata: state.metadata.get(&metadata_id),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TaskJson<'a> {
+    #[serde(flatten)]
+    task: WithMetadata<'a, Task>,
+    warnings: &'a [Warning],
+}
+
+impl<'a> TaskJson<'a> {
+    fn new(task: &'a Task, state: &'a ConsoleState) -> Self {
+        Self {
+            task: WithMetadata::new(task, task.metadata_id, state),
+            warnings: state
+                .warnings
+                .get(&task.id)
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn not_found(message: String) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message,
+        }
+    }
+
+    fn forbidden(err: anyhow::Error) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: err.to_string(),
+        }
+    }
+
+    fn bad_gateway(err: anyhow::Error) -> Self {
+        Self {
+            status: StatusCode::BAD_GATEWAY,
+            message: format!("Failed to connect. Error: {:#}", err),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn task(id: u64, polls: u64, completed: bool) -> Arc<Task> {
+        let mut task = Task::try_from(console_api::tasks::Task {
+            id: Some(console_api::Id { id }),
+            metadata: Some(console_api::MetaId { id: 1 }),
+            location: Some(console_api::Location {
+                file: Some("src/main.rs".to_owned()),
+                module_path: None,
+                line: Some(1),
+                column: Some(1),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        task.stats = Some(TaskStats {
+            dropped_at: completed.then(SystemTime::now),
+            created_at: None,
+            busy_time: None,
+            last_poll_started: None,
+            last_poll_ended: None,
+            polls,
+            wakes: 0,
+            waker_clones: 0,
+            waker_drops: 0,
+            last_wake: None,
+            self_wakes: 0,
+        });
+        Arc::new(task)
+    }
+
+    fn state(tasks: &[&Arc<Task>]) -> ConsoleState {
+        ConsoleState {
+            tasks: tasks
+                .iter()
+                .map(|task| (task.id, Arc::clone(task)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn types(events: &[StateEvent]) -> Vec<(&'static str, u64)> {
+        events
+            .iter()
+            .map(|event| match event {
+                StateEvent::TaskSpawned { task } => ("spawned", task.id.0),
+                StateEvent::TaskCompleted { id } => ("completed", id.0),
+                StateEvent::TaskStatsChanged { id, .. } => ("stats", id.0),
+                StateEvent::ResourceCreated { resource } => ("created", resource.id.0),
+                StateEvent::ResourceDropped { id } => ("dropped", id.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_tasks_have_no_events() {
+        let task = task(1, 1, false);
+
+        assert!(StateEvent::diff(&state(&[&task]), &state(&[&task])).is_empty());
+    }
+
+    #[test]
+    fn task_lifecycle() {
+        let spawned = task(1, 0, false);
+        let polled = task(1, 1, false);
+        let completed = task(1, 1, true);
+
+        assert_eq!(
+            types(&StateEvent::diff(&state(&[]), &state(&[&spawned]))),
+            [("spawned", 1)]
+        );
+        assert_eq!(
+            types(&StateEvent::diff(&state(&[&spawned]), &state(&[&polled]))),
+            [("stats", 1)]
+        );
+        assert_eq!(
+            types(&StateEvent::diff(&state(&[&polled]), &state(&[&completed]))),
+            [("stats", 1), ("completed", 1)]
+        );
+        // once removed by retention there is nothing left to report
+        assert!(StateEvent::diff(&state(&[&completed]), &state(&[])).is_empty());
+    }
+
+    #[test]
+    fn tasks_completed_before_being_seen() {
+        let completed = task(1, 1, true);
+
+        assert_eq!(
+            types(&StateEvent::diff(&state(&[]), &state(&[&completed]))),
+            [("spawned", 1), ("completed", 1)]
+        );
+    }
+
+    #[test]
+    fn tasks_removed_before_being_seen_completed() {
+        let running = task(1, 1, false);
+        let other = task(2, 1, false);
+
+        assert_eq!(
+            types(&StateEvent::diff(
+                &state(&[&running, &other]),
+                &state(&[&other])
+            )),
+            [("completed", 1)]
+        );
+    }
+
+    #[test]
+    fn filter_and_sort_by_busy_time() {
+        let busy = |id, millis| {
+            let mut task = Task::clone(&task(id, 1, false));
+            task.stats.as_mut().unwrap().busy_time = Some(Duration::from_millis(millis));
+            Arc::new(task)
+        };
+        let tasks = [busy(1, 500), busy(2, 3000), busy(3, 2000)];
+        let state = ConsoleState {
+            now: Some(SystemTime::now()),
+            ..state(&tasks.iter().collect::<Vec<_>>())
+        };
+
+        let query = ListQuery {
+            filter: Some("busy>1s".to_owned()),
+            sort: Some("-busy".to_owned()),
+            limit: None,
+        };
+        let ids = query
+            .apply(state.tasks.values().map(|task| &**task), &state)
+            .into_iter()
+            .map(|task| task.id.0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, [2, 3]);
+    }
+}
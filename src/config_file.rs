@@ -0,0 +1,235 @@
+//! The TOML file passed with `--config`.
+//!
+//! ```toml
+//! retention = "30s"
+//!
+//! [ui]
+//! hide_completed = true
+//!
+//! [targets]
+//! api-gateway = "10.1.2.3:6669"
+//! local = "unix:///tmp/console.sock"
+//! worker = { addr = "10.1.2.4:6669", auto_connect = true }
+//!
+//! [targets.production]
+//! addr = "console.example.com:6669"
+//! tls = { ca_cert = "ca.pem", client_cert = "client.pem", client_key = "client.key" }
+//! ```
+//!
+//! Relative TLS paths are relative to the directory containing the config file.
+
+use crate::{console_addr::ConsoleAddr, tls::TlsOptions, watch_stream::Retention};
+use anyhow::Context as _;
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Used unless `--retention` is given.
+    retention: Option<String>,
+    #[serde(default)]
+    pub ui: UiPreferences,
+    #[serde(default)]
+    targets: BTreeMap<String, TargetConfig>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config: Self = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        if let Some(dir) = path.parent() {
+            for target in config.targets.values_mut() {
+                if let TargetConfig::Table(TargetTable { tls: Some(tls), .. }) = target {
+                    *tls = tls.relative_to(dir);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn retention(&self) -> anyhow::Result<Option<Retention>> {
+        self.retention
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .context("Invalid `retention` in config file")
+    }
+
+    pub fn targets(&self) -> anyhow::Result<Targets> {
+        let targets = self
+            .targets
+            .iter()
+            .map(|(name, target)| {
+                let (addr, auto_connect, tls) = match target {
+                    TargetConfig::Addr(addr) => (addr, false, None),
+                    TargetConfig::Table(TargetTable {
+                        addr,
+                        auto_connect,
+                        tls,
+                    }) => (addr, *auto_connect, tls.clone()),
+                };
+                let addr = addr
+                    .parse()
+                    .with_context(|| format!("Invalid address for target `{}`", name))?;
+                let target = Target {
+                    addr,
+                    auto_connect,
+                    tls,
+                };
+                Ok((name.clone(), target))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Targets(Arc::new(targets)))
+    }
+}
+
+/// Defaults for the UI.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UiPreferences {
+    /// Hide completed tasks until "Show completed" is clicked.
+    #[serde(default)]
+    pub hide_completed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TargetConfig {
+    Addr(String),
+    Table(TargetTable),
+}
+
+// a separate struct because `deny_unknown_fields` can't be used on enum variants
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetTable {
+    addr: String,
+    #[serde(default)]
+    auto_connect: bool,
+    tls: Option<TlsOptions>,
+}
+
+/// Consoles with names from the config file.
+#[derive(Debug, Default, Clone)]
+pub struct Targets(Arc<BTreeMap<String, Target>>);
+
+#[derive(Debug)]
+pub struct Target {
+    pub addr: ConsoleAddr,
+    /// Connect at startup and stay connected.
+    pub auto_connect: bool,
+    pub tls: Option<TlsOptions>,
+}
+
+impl Targets {
+    pub fn get(&self, name: &str) -> Option<&Target> {
+        self.0.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Target)> {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Load `contents` from a config file in a new temporary directory, which is returned too.
+    fn load(contents: &str) -> (PathBuf, anyhow::Result<ConfigFile>) {
+        let dir = std::env::temp_dir().join(format!("config-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("console.toml");
+        std::fs::write(&path, contents).unwrap();
+
+        let config = ConfigFile::load(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        (dir, config)
+    }
+
+    fn targets(contents: &str) -> Targets {
+        load(contents).1.unwrap().targets().unwrap()
+    }
+
+    #[test]
+    fn string_and_table_targets() {
+        let targets = targets(
+            r#"
+            [targets]
+            plain = "10.1.2.3:6669"
+            inline = { addr = "unix:///tmp/console.sock", auto_connect = true }
+
+            [targets.table]
+            addr = "console.example.com:6669"
+            "#,
+        );
+
+        let plain = targets.get("plain").unwrap();
+        assert_eq!(plain.addr, "10.1.2.3:6669".parse().unwrap());
+        assert!(!plain.auto_connect);
+        assert!(plain.tls.is_none());
+
+        let inline = targets.get("inline").unwrap();
+        assert_eq!(inline.addr, ConsoleAddr::Unix("/tmp/console.sock".into()));
+        assert!(inline.auto_connect);
+
+        let table = targets.get("table").unwrap();
+        assert_eq!(table.addr, "console.example.com:6669".parse().unwrap());
+        assert!(!table.auto_connect);
+
+        assert!(targets.get("missing").is_none());
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        for contents in [
+            r#"retenton = "5s""#,
+            "[ui]\nhide_complete = true",
+            r#"targets.a = { addr = "127.0.0.1:6669", auto_conect = true }"#,
+            r#"targets.a = { addr = "127.0.0.1:6669", tls = { ca = "ca.pem" } }"#,
+        ] {
+            assert!(load(contents).1.is_err(), "{}", contents);
+        }
+    }
+
+    #[test]
+    fn tls_paths_are_relative_to_the_config_file() {
+        let (dir, config) = load(
+            r#"
+            [targets.a]
+            addr = "127.0.0.1:6669"
+            tls = { ca_cert = "certs/ca.pem", client_cert = "/etc/console/client.pem", domain = "console" }
+            "#,
+        );
+        let targets = config.unwrap().targets().unwrap();
+        let tls = targets.get("a").unwrap().tls.as_ref().unwrap();
+
+        assert_eq!(tls.ca_cert, Some(dir.join("certs/ca.pem")));
+        assert_eq!(tls.client_cert, Some("/etc/console/client.pem".into()));
+        assert_eq!(tls.client_key, None);
+        assert_eq!(tls.domain.as_deref(), Some("console"));
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        let config = load(r#"targets.a = "not an address""#).1.unwrap();
+        assert!(config.targets().is_err());
+
+        let config = load(r#"retention = "5 parsecs""#).1.unwrap();
+        assert!(config.retention().is_err());
+
+        let config = load(r#"retention = "5s""#).1.unwrap();
+        assert_eq!(
+            config.retention().unwrap(),
+            Some(Retention::Duration(std::time::Duration::from_secs(5)))
+        );
+        assert_eq!(load("").1.unwrap().retention().unwrap(), None);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::{allowlist::Allowlist, tls::TlsOptions};
+use anyhow::Context as _;
+use axum::{
+    async_trait,
+    extract::{FromRequest, Path, RequestParts},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr};
+use tokio::net::TcpStream;
+use tonic::transport::{Channel, Endpoint};
+
+/// Where a console is listening.
+///
+/// Parsed from `host:port`, where the host is a DNS name, an IPv4 address or an IPv6 address in
+/// brackets such as `[::1]:6669`, or from `unix:///path/to/socket`.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum ConsoleAddr {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
+impl ConsoleAddr {
+    /// The path segments identifying this console in URLs, such as `tcp/127.0.0.1/6669` or
+    /// `unix/%2Ftmp%2Fconsole.sock`.
+    pub fn path(&self) -> String {
+        match self {
+            ConsoleAddr::Tcp { host, port } => format!("tcp/{}/{}", encode_segment(host), port),
+            ConsoleAddr::Unix(path) => {
+                format!("unix/{}", encode_segment(&path.to_string_lossy()))
+            }
+        }
+    }
+
+    /// Route patterns matching [`ConsoleAddr::path`], between `prefix` and `suffix`.
+    pub(crate) fn route_patterns(prefix: &str, suffix: &str) -> [String; 2] {
+        [
+            format!("{}/tcp/:host/:port{}", prefix, suffix),
+            format!("{}/unix/:path{}", prefix, suffix),
+        ]
+    }
+
+    /// The inverse of [`ConsoleAddr::path`], given the route parameters from
+    /// [`ConsoleAddr::route_patterns`]. They have already been percent decoded by [`Path`].
+    fn from_params(params: &HashMap<String, String>) -> anyhow::Result<Self> {
+        if let Some(path) = params.get("path") {
+            anyhow::ensure!(
+                path.starts_with('/'),
+                "Unix socket paths must be absolute, got `{}`",
+                path
+            );
+            return Ok(ConsoleAddr::Unix(path.into()));
+        }
+
+        let host = params.get("host").context("Missing host")?;
+        let port = params.get("port").context("Missing port")?;
+        anyhow::ensure!(!host.is_empty(), "Missing host");
+
+        Ok(ConsoleAddr::Tcp {
+            host: host.clone(),
+            port: port
+                .parse()
+                .with_context(|| format!("Invalid port `{}`", port))?,
+        })
+    }
+
+    /// A channel that connects to the console when first used, over TLS if `tls` is given.
+    ///
+    /// Every TCP connection, including reconnects, only goes to addresses `allowlist` allows.
+    pub fn connect_lazy(
+        &self,
+        tls: Option<&TlsOptions>,
+        allowlist: &Allowlist,
+    ) -> anyhow::Result<Channel> {
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        let mut endpoint = match self {
+            ConsoleAddr::Tcp { host, port } => {
+                // the connector resolves the address itself, so the uri just needs to be valid,
+                // which it isn't with an IPv6 zone such as `%eth0` in it
+                let host = host.split('%').next().unwrap_or_default().to_owned();
+                let authority = ConsoleAddr::Tcp { host, port: *port };
+                Endpoint::from_shared(format!("{}://{}", scheme, authority))?
+            }
+            // the uri is required but ignored by the connector, apart from being the default
+            // server name for TLS
+            ConsoleAddr::Unix(_) => Endpoint::from_shared(format!("{}://localhost", scheme))?,
+        };
+
+        if let Some(tls) = tls {
+            let config = tls
+                .client_config()
+                .with_context(|| format!("Invalid TLS options for {}", self))?;
+            endpoint = endpoint.tls_config(config)?;
+        }
+
+        match self {
+            ConsoleAddr::Tcp { .. } => {
+                let addr = self.clone();
+                let allowlist = allowlist.clone();
+                let channel =
+                    endpoint.connect_with_connector_lazy(tower::service_fn(move |_| {
+                        let addr = addr.clone();
+                        let allowlist = allowlist.clone();
+                        async move { connect_tcp(&addr, &allowlist).await }
+                    }))?;
+                Ok(channel)
+            }
+            #[cfg(unix)]
+            ConsoleAddr::Unix(path) => {
+                let path = path.clone();
+                let channel =
+                    endpoint.connect_with_connector_lazy(tower::service_fn(move |_| {
+                        tokio::net::UnixStream::connect(path.clone())
+                    }))?;
+                Ok(channel)
+            }
+            #[cfg(not(unix))]
+            ConsoleAddr::Unix(_) => {
+                anyhow::bail!("Unix sockets are not supported on this platform")
+            }
+        }
+    }
+}
+
+/// Connect to the first of the addresses `addr` resolves to that accepts, if they're all allowed.
+async fn connect_tcp(addr: &ConsoleAddr, allowlist: &Allowlist) -> anyhow::Result<TcpStream> {
+    let mut last_err = None;
+    for socket_addr in allowlist.resolve(addr).await? {
+        match TcpStream::connect(socket_addr).await {
+            Ok(stream) => {
+                // like tonic's own connector
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err
+        .map(anyhow::Error::from)
+        .unwrap_or_else(|| anyhow::anyhow!("No addresses to connect to")))
+    .with_context(|| format!("Failed to connect to {}", addr))
+}
+
+impl Default for ConsoleAddr {
+    fn default() -> Self {
+        ConsoleAddr::Tcp {
+            host: "127.0.0.1".to_owned(),
+            port: 6669,
+        }
+    }
+}
+
+impl fmt::Display for ConsoleAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsoleAddr::Tcp { host, port } if host.contains(':') => {
+                write!(f, "[{}]:{}", host, port)
+            }
+            ConsoleAddr::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            ConsoleAddr::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for ConsoleAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix://") {
+            anyhow::ensure!(
+                path.starts_with('/'),
+                "Unix socket paths must be absolute, got `{}`",
+                s
+            );
+            return Ok(ConsoleAddr::Unix(path.into()));
+        }
+
+        let (host, port) = s
+            .rsplit_once(':')
+            .with_context(|| format!("Expected `host:port`, got `{}`", s))?;
+
+        let host = if let Some(host) = host.strip_prefix('[') {
+            host.strip_suffix(']')
+                .with_context(|| format!("Missing `]` in `{}`", s))?
+        } else {
+            anyhow::ensure!(
+                !host.contains(':'),
+                "IPv6 addresses must be in brackets, such as `[::1]:6669`, got `{}`",
+                s
+            );
+            host
+        };
+        anyhow::ensure!(!host.is_empty(), "Missing host in `{}`", s);
+
+        Ok(ConsoleAddr::Tcp {
+            host: host.to_owned(),
+            port: port
+                .parse()
+                .with_context(|| format!("Invalid port `{}`", port))?,
+        })
+    }
+}
+
+/// Extracts the console from a route matching one of [`ConsoleAddr::route_patterns`].
+#[async_trait]
+impl<B> FromRequest<B> for ConsoleAddr
+where
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Path(params) = Path::<HashMap<String, String>>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        ConsoleAddr::from_params(&params)
+            .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()).into_response())
+    }
+}
+
+/// Percent encode everything but unreserved characters, so the result is a single path segment.
+pub(crate) fn encode_segment(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use console_api::instrument::PauseRequest;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn tcp(host: &str, port: u16) -> ConsoleAddr {
+        ConsoleAddr::Tcp {
+            host: host.to_owned(),
+            port,
+        }
+    }
+
+    fn unix(path: &str) -> ConsoleAddr {
+        ConsoleAddr::Unix(path.into())
+    }
+
+    /// Percent decode like [`Path`] does.
+    fn decode(segment: &str) -> String {
+        let mut out = Vec::new();
+        let mut bytes = segment.bytes();
+        while let Some(byte) = bytes.next() {
+            if byte == b'%' {
+                let hex = [bytes.next().unwrap(), bytes.next().unwrap()];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).unwrap(), 16).unwrap());
+            } else {
+                out.push(byte);
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Extract the console from `path` like a request matching [`ConsoleAddr::route_patterns`]
+    /// would.
+    fn route(path: &str) -> anyhow::Result<ConsoleAddr> {
+        let segments = path.split('/').collect::<Vec<_>>();
+        let params = match segments[..] {
+            ["tcp", host, port] => [("host", host), ("port", port)].to_vec(),
+            ["unix", path] => [("path", path)].to_vec(),
+            _ => anyhow::bail!("No route matches `{}`", path),
+        };
+
+        let params = params
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), decode(value)))
+            .collect();
+        ConsoleAddr::from_params(&params)
+    }
+
+    fn addrs() -> Vec<(&'static str, ConsoleAddr)> {
+        vec![
+            ("127.0.0.1:6669", tcp("127.0.0.1", 6669)),
+            ("[::1]:6669", tcp("::1", 6669)),
+            ("[fe80::1%eth0]:6669", tcp("fe80::1%eth0", 6669)),
+            ("console.example.com:80", tcp("console.example.com", 80)),
+            // hosts that look like the other kinds of path
+            ("unix:6669", tcp("unix", 6669)),
+            ("by-name:6669", tcp("by-name", 6669)),
+            ("unix:///tmp/console.sock", unix("/tmp/console.sock")),
+            (
+                "unix:///tmp/my consoles/100%/a.sock",
+                unix("/tmp/my consoles/100%/a.sock"),
+            ),
+        ]
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for (s, addr) in addrs() {
+            assert_eq!(s.parse::<ConsoleAddr>().unwrap(), addr, "{}", s);
+            assert_eq!(addr.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for s in [
+            "",
+            "localhost",
+            ":6669",
+            "::1:6669",
+            "[::1:6669",
+            "localhost:http",
+            "localhost:70000",
+            "unix://relative.sock",
+        ] {
+            assert!(s.parse::<ConsoleAddr>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(tcp("127.0.0.1", 6669).path(), "tcp/127.0.0.1/6669");
+        assert_eq!(tcp("::1", 6669).path(), "tcp/%3A%3A1/6669");
+        assert_eq!(tcp("unix", 6669).path(), "tcp/unix/6669");
+        assert_eq!(
+            unix("/tmp/my consoles/100%/a.sock").path(),
+            "unix/%2Ftmp%2Fmy%20consoles%2F100%25%2Fa.sock"
+        );
+
+        for (_, addr) in addrs() {
+            assert_eq!(route(&addr.path()).unwrap(), addr);
+        }
+    }
+
+    #[test]
+    fn invalid_paths() {
+        for path in ["tcp/localhost/http", "tcp//6669", "unix/relative.sock"] {
+            assert!(route(path).is_err(), "{}", path);
+        }
+    }
+
+    /// A listener on localhost that counts the connections made to it and closes them straight
+    /// away.
+    async fn counting_listener() -> (ConsoleAddr, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp("127.0.0.1", listener.local_addr().unwrap().port());
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn({
+            let connections = Arc::clone(&connections);
+            async move {
+                while listener.accept().await.is_ok() {
+                    connections.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        (addr, connections)
+    }
+
+    async fn request(channel: Channel) {
+        let mut client = crate::InstrumentClient::new(channel);
+        assert!(client.pause(PauseRequest {}).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connections_are_checked_every_time() {
+        let (addr, connections) = counting_listener().await;
+        let allowlist = |rule: &str| Allowlist::new(vec![rule.parse().unwrap()]);
+
+        request(addr.connect_lazy(None, &allowlist("127.0.0.1")).unwrap()).await;
+        let allowed = connections.load(Ordering::SeqCst);
+        assert!(allowed > 0);
+
+        // the connection is closed every time, so each request reconnects
+        let channel = addr.connect_lazy(None, &allowlist("10.0.0.0/8")).unwrap();
+        for _ in 0..3 {
+            request(channel.clone()).await;
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), allowed);
+    }
+
+    #[tokio::test]
+    async fn ipv6_zones() {
+        let addr = tcp("fe80::1%eth0", 6669);
+
+        let tls = TlsOptions {
+            domain: Some("console.example.com".to_owned()),
+            ..Default::default()
+        };
+
+        for tls in [None, Some(tls)] {
+            assert!(addr
+                .connect_lazy(tls.as_ref(), &Allowlist::default())
+                .is_ok());
+        }
+    }
+}
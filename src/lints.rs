@@ -0,0 +1,385 @@
+//! Lints that flag tasks matching known bad patterns.
+//!
+//! The linter runs on every new [`ConsoleState`] and stores the results in
+//! [`ConsoleState::warnings`]. Project specific lints can be added by implementing [`Lint`] and
+//! registering it with [`Linter::with_lint`].
+
+use crate::watch_stream::{ConsoleState, Task, TaskId, TaskState};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, UNIX_EPOCH},
+};
+
+pub trait Lint: Send + Sync + 'static {
+    /// Short human readable name, used when summarizing warnings.
+    fn name(&self) -> &str;
+
+    /// Check a single task. Returns a message describing the problem if the task is suspicious.
+    fn check(&self, task: &Task, state: &ConsoleState) -> Option<String>;
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.lint, self.message)
+    }
+}
+
+pub struct Linter {
+    lints: Vec<Box<dyn Lint>>,
+}
+
+impl Linter {
+    /// Create a linter without any lints.
+    pub fn empty() -> Self {
+        Self { lints: Vec::new() }
+    }
+
+    pub fn with_lint<L>(mut self, lint: L) -> Self
+    where
+        L: Lint,
+    {
+        self.lints.push(Box::new(lint));
+        self
+    }
+
+    pub fn check(&self, state: &ConsoleState) -> BTreeMap<TaskId, Vec<Warning>> {
+        let mut warnings = BTreeMap::new();
+
+        for task in state.tasks.values() {
+            let task_warnings = self
+                .lints
+                .iter()
+                .filter_map(|lint| {
+                    let message = lint.check(task, state)?;
+                    Some(Warning {
+                        lint: lint.name().to_owned(),
+                        message,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            if !task_warnings.is_empty() {
+                warnings.insert(task.id, task_warnings);
+            }
+        }
+
+        warnings
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::empty()
+            .with_lint(SelfWakes::default())
+            .with_lint(LostWaker)
+            .with_lint(NeverYielded::default())
+            .with_lint(NeverPolled::default())
+    }
+}
+
+/// Flags tasks where more than `max_percent` of all wakes are self-wakes.
+pub struct SelfWakes {
+    pub max_percent: u64,
+}
+
+impl Default for SelfWakes {
+    fn default() -> Self {
+        Self { max_percent: 50 }
+    }
+}
+
+impl Lint for SelfWakes {
+    fn name(&self) -> &str {
+        "self-wakes"
+    }
+
+    fn check(&self, task: &Task, _state: &ConsoleState) -> Option<String> {
+        let stats = task.stats.as_ref()?;
+
+        if stats.wakes == 0 {
+            return None;
+        }
+
+        let percent = stats.self_wakes * 100 / stats.wakes;
+        if percent > self.max_percent {
+            Some(format!(
+                "has woken itself for {}% of its wakes ({} of {})",
+                percent, stats.self_wakes, stats.wakes
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Flags idle tasks that no longer have any wakers and therefore will never be woken again.
+pub struct LostWaker;
+
+impl Lint for LostWaker {
+    fn name(&self) -> &str {
+        "lost waker"
+    }
+
+    fn check(&self, task: &Task, _state: &ConsoleState) -> Option<String> {
+        let stats = task.stats.as_ref()?;
+
+        let idle = matches!(task.state(), TaskState::Idle);
+        if idle && stats.polls > 0 && stats.waker_count() == 0 {
+            Some("is idle and has no wakers, it will never be woken".to_owned())
+        } else {
+            None
+        }
+    }
+}
+
+/// Flags running tasks whose current poll has been running for longer than `threshold`.
+pub struct NeverYielded {
+    pub threshold: Duration,
+}
+
+impl Default for NeverYielded {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Lint for NeverYielded {
+    fn name(&self) -> &str {
+        "never yielded"
+    }
+
+    fn check(&self, task: &Task, state: &ConsoleState) -> Option<String> {
+        if !task.is_running() {
+            return None;
+        }
+
+        let last_poll_started = task.stats.as_ref()?.last_poll_started?;
+        let now = state.now?.duration_since(UNIX_EPOCH).ok()?;
+        let poll_duration = now.checked_sub(last_poll_started)?;
+
+        if poll_duration > self.threshold {
+            Some(format!(
+                "has been running for {:?} without yielding",
+                poll_duration
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Flags tasks that have existed for longer than `grace_period` without being polled.
+pub struct NeverPolled {
+    pub grace_period: Duration,
+}
+
+impl Default for NeverPolled {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Lint for NeverPolled {
+    fn name(&self) -> &str {
+        "never polled"
+    }
+
+    fn check(&self, task: &Task, state: &ConsoleState) -> Option<String> {
+        let stats = task.stats.as_ref()?;
+
+        if stats.polls != 0 || task.is_completed() {
+            return None;
+        }
+
+        let age = state.elapsed_since(stats.created_at?)?;
+        if age > self.grace_period {
+            Some(format!("has never been polled in {:?}", age))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watch_stream::TaskStats;
+    use std::time::SystemTime;
+
+    fn at(secs: f64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs_f64(secs)
+    }
+
+    fn stats() -> TaskStats {
+        TaskStats {
+            dropped_at: None,
+            created_at: Some(at(100.0)),
+            busy_time: None,
+            last_poll_started: None,
+            last_poll_ended: None,
+            polls: 0,
+            wakes: 0,
+            waker_clones: 0,
+            waker_drops: 0,
+            last_wake: None,
+            self_wakes: 0,
+        }
+    }
+
+    fn task(stats: TaskStats) -> Task {
+        let mut task = Task::try_from(console_api::tasks::Task {
+            id: Some(console_api::Id { id: 1 }),
+            metadata: Some(console_api::MetaId { id: 1 }),
+            location: Some(console_api::Location {
+                file: Some("src/main.rs".to_owned()),
+                module_path: None,
+                line: Some(1),
+                column: Some(1),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        task.stats = Some(stats);
+        task
+    }
+
+    fn state(now: f64) -> ConsoleState {
+        ConsoleState {
+            now: Some(at(now)),
+            ..Default::default()
+        }
+    }
+
+    fn flags(lint: &dyn Lint, stats: TaskStats, now: f64) -> bool {
+        lint.check(&task(stats), &state(now)).is_some()
+    }
+
+    #[test]
+    fn self_wakes() {
+        let lint = SelfWakes::default();
+
+        for (self_wakes, wakes, expected) in [(5, 10, false), (6, 10, true), (0, 0, false)] {
+            let stats = TaskStats {
+                self_wakes,
+                wakes,
+                ..stats()
+            };
+            assert_eq!(
+                flags(&lint, stats, 100.0),
+                expected,
+                "{} of {}",
+                self_wakes,
+                wakes
+            );
+        }
+    }
+
+    #[test]
+    fn lost_waker() {
+        let idle = TaskStats {
+            polls: 1,
+            last_poll_started: Some(Duration::from_secs(101)),
+            last_poll_ended: Some(Duration::from_secs(102)),
+            ..stats()
+        };
+
+        assert!(flags(&LostWaker, idle.clone(), 110.0));
+        assert!(!flags(
+            &LostWaker,
+            TaskStats {
+                waker_clones: 2,
+                waker_drops: 1,
+                ..idle.clone()
+            },
+            110.0
+        ));
+        assert!(flags(
+            &LostWaker,
+            TaskStats {
+                waker_clones: 2,
+                waker_drops: 2,
+                ..idle.clone()
+            },
+            110.0
+        ));
+        // never polled tasks are covered by `NeverPolled`
+        assert!(!flags(
+            &LostWaker,
+            TaskStats {
+                polls: 0,
+                ..idle.clone()
+            },
+            110.0
+        ));
+        assert!(!flags(
+            &LostWaker,
+            TaskStats {
+                dropped_at: Some(at(103.0)),
+                ..idle
+            },
+            110.0
+        ));
+    }
+
+    #[test]
+    fn never_yielded() {
+        let lint = NeverYielded::default();
+        // poll times are durations since the epoch, like the console's `now`
+        let running = TaskStats {
+            polls: 1,
+            last_poll_started: Some(Duration::from_secs(100)),
+            ..stats()
+        };
+
+        assert!(!flags(&lint, running.clone(), 100.5));
+        assert!(!flags(&lint, running.clone(), 101.0));
+        assert!(flags(&lint, running.clone(), 101.5));
+        // a poll that started after `now` because of clock skew
+        assert!(!flags(&lint, running.clone(), 99.0));
+        assert!(!flags(
+            &lint,
+            TaskStats {
+                last_poll_ended: Some(Duration::from_secs(101)),
+                ..running
+            },
+            200.0
+        ));
+    }
+
+    #[test]
+    fn never_polled() {
+        let lint = NeverPolled::default();
+
+        assert!(!flags(&lint, stats(), 100.5));
+        assert!(!flags(&lint, stats(), 101.0));
+        assert!(flags(&lint, stats(), 101.5));
+        assert!(!flags(
+            &lint,
+            TaskStats {
+                polls: 1,
+                ..stats()
+            },
+            200.0
+        ));
+        assert!(!flags(
+            &lint,
+            TaskStats {
+                dropped_at: Some(at(100.5)),
+                ..stats()
+            },
+            200.0
+        ));
+    }
+}
@@ -1,10 +1,11 @@
 macro_rules! columns_enum {
     (
-        pub(crate) enum $ident:ident {
-            $($variant:ident),* $(,)?
+        $vis:vis enum $ident:ident {
+            $($variant:ident $(= $name:literal)?),* $(,)?
         }
     ) => {
-        pub(crate) enum $ident {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+        $vis enum $ident {
             $($variant),*
         }
 
@@ -18,10 +19,13 @@ macro_rules! columns_enum {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match self {
                     $(
-                        Self::$variant => write!(f, stringify!($variant)),
+                        Self::$variant => f.write_str(columns_enum!(@name $variant $($name)?)),
                     )*
                 }
             }
         }
     };
+
+    (@name $variant:ident $name:literal) => { $name };
+    (@name $variant:ident) => { stringify!($variant) };
 }
@@ -1,8 +1,17 @@
-use crate::watch_stream::ConsoleSubscriptions;
+use crate::{
+    allowlist::{AllowRule, Allowlist},
+    config_file::ConfigFile,
+    console_addr::ConsoleAddr,
+    lints::Linter,
+    recording::Recorder,
+    replay::Replays,
+    tls::TlsDir,
+    watch_stream::{ConsoleSubscriptions, Retention},
+};
 use axum::Router;
 use axum_flash::Key;
 use clap::Parser;
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 use tower::ServiceBuilder;
 use tower_http::ServiceBuilderExt;
 use tracing_subscriber::{prelude::*, EnvFilter};
@@ -10,14 +19,67 @@ use tracing_subscriber::{prelude::*, EnvFilter};
 #[macro_use]
 mod macros;
 
+mod allowlist;
+mod api;
+mod config_file;
+mod console_addr;
+mod lints;
+mod metrics;
+mod recording;
+mod replay;
 mod routes;
+mod tls;
 mod views;
 mod watch_stream;
 
 #[derive(Debug, Parser)]
 struct Config {
+    /// TOML file with named targets and defaults.
+    #[clap(long, env = "TOKIO_CONSOLE_CONFIG")]
+    config: Option<PathBuf>,
+
     #[clap(long, env = "TOKIO_CONSOLE_BIND_ADDR", default_value = "0.0.0.0:3000")]
     bind_addr: SocketAddr,
+
+    /// Record every console session to files in this directory.
+    #[clap(long, env = "TOKIO_CONSOLE_RECORD")]
+    record: Option<PathBuf>,
+
+    /// Directory used for recordings started from the UI and for replays.
+    #[clap(
+        long,
+        env = "TOKIO_CONSOLE_RECORDINGS_DIR",
+        default_value = "recordings"
+    )]
+    recordings_dir: PathBuf,
+
+    /// How long to keep completed tasks, dropped resources and dropped async ops around.
+    ///
+    /// Either a duration such as `30s` or `5m`, the number of most recently dropped entries to
+    /// keep such as `100`, or `forever`.
+    ///
+    /// Defaults to the `retention` in the config file, or `5s`.
+    #[clap(long, env = "TOKIO_CONSOLE_RETENTION")]
+    retention: Option<Retention>,
+
+    /// Consoles to always stay subscribed to, such as `127.0.0.1:6669`, so they show up in
+    /// `/metrics` even when nobody has them open.
+    #[clap(
+        long = "metrics-console",
+        env = "TOKIO_CONSOLE_METRICS_CONSOLES",
+        use_delimiter = true
+    )]
+    metrics_consoles: Vec<ConsoleAddr>,
+
+    /// Consoles users may connect to, such as `10.0.0.0/8:6669`, `*.example.com` or
+    /// `unix:///run/consoles/*`. Everything is allowed if none are given.
+    #[clap(long = "allow", env = "TOKIO_CONSOLE_ALLOW", use_delimiter = true)]
+    allow: Vec<AllowRule>,
+
+    /// Directory containing the TLS certificates and keys users may pick when opening a console.
+    /// TLS files can only be set in the config file if this isn't given.
+    #[clap(long, env = "TOKIO_CONSOLE_TLS_DIR")]
+    tls_dir: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -35,14 +97,77 @@ async fn main() -> anyhow::Result<()> {
     let config = Config::parse();
     tracing::trace!(?config);
 
+    let config_file = match &config.config {
+        Some(path) => ConfigFile::load(path)?,
+        None => ConfigFile::default(),
+    };
+    let targets = config_file.targets()?;
+
+    let retention = match config.retention {
+        Some(retention) => retention,
+        None => config_file.retention()?.unwrap_or_default(),
+    };
+
     let key = Key::generate();
 
+    let recorder = Recorder::new(
+        config
+            .record
+            .clone()
+            .unwrap_or_else(|| config.recordings_dir.clone()),
+    );
+
+    let linter = Arc::new(Linter::default());
+    let replays = Replays::new(
+        config.recordings_dir.clone(),
+        Arc::clone(&linter),
+        retention,
+    );
+
+    let allowlist = Allowlist::new(config.allow.clone()).trust(
+        config
+            .metrics_consoles
+            .iter()
+            .cloned()
+            .chain(targets.iter().map(|(_, target)| target.addr.clone())),
+    );
+
+    let mut subscriptions = ConsoleSubscriptions::new(linter)
+        .retention(retention)
+        .allowlist(allowlist.clone());
+    if config.record.is_some() {
+        subscriptions = subscriptions.record_all(recorder.clone());
+    }
+
+    for addr in &config.metrics_consoles {
+        subscriptions.keep_subscribed(addr.clone());
+    }
+
+    for (name, target) in targets.iter() {
+        if target.tls.is_some() {
+            subscriptions.set_tls(target.addr.clone(), target.tls.clone());
+        }
+
+        if target.auto_connect {
+            tracing::debug!(%name, addr = ?target.addr, "auto connecting");
+            subscriptions.keep_subscribed(target.addr.clone());
+        }
+    }
+
     let app = Router::new()
         .merge(routes::all())
+        .merge(api::routes())
+        .merge(metrics::routes())
         .route("/assets/live-view.js", axum_live_view::precompiled_js())
         .layer(
             ServiceBuilder::new()
-                .add_extension(ConsoleSubscriptions::default())
+                .add_extension(subscriptions)
+                .add_extension(recorder)
+                .add_extension(replays)
+                .add_extension(targets)
+                .add_extension(allowlist)
+                .add_extension(TlsDir(config.tls_dir.clone()))
+                .add_extension(config_file.ui)
                 .layer(
                     axum_flash::layer(key)
                         .use_secure_cookies(false)
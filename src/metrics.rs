@@ -0,0 +1,272 @@
+//! Prometheus metrics for every console we're subscribed to.
+
+use crate::{
+    console_addr::ConsoleAddr,
+    watch_stream::{ConnectionStatus, ConsoleState, ConsoleSubscriptions, TaskState},
+};
+use axum::{
+    extract::Extension,
+    http::header,
+    response::{Headers, IntoResponse},
+    routing::get,
+    Router,
+};
+use std::{collections::BTreeMap, fmt::Write as _, time::Duration};
+
+pub fn routes() -> Router {
+    Router::new().route("/metrics", get(metrics))
+}
+
+async fn metrics(Extension(subscriptions): Extension<ConsoleSubscriptions>) -> impl IntoResponse {
+    let mut consoles = Vec::new();
+    for (addr, watch) in subscriptions.active().await {
+        let metrics = ConsoleMetrics::new(&watch.borrow());
+        consoles.push((addr, metrics));
+    }
+    consoles.sort_by(|(a, _), (b, _)| a.to_string().cmp(&b.to_string()));
+
+    (
+        Headers([(header::CONTENT_TYPE, "text/plain; version=0.0.4")]),
+        render(&consoles),
+    )
+}
+
+/// The numbers we export for one console.
+#[derive(Default)]
+struct ConsoleMetrics {
+    up: bool,
+    tasks: BTreeMap<(&'static str, String), usize>,
+    polls: u64,
+    busy: Duration,
+    resources: BTreeMap<String, usize>,
+}
+
+impl ConsoleMetrics {
+    fn new(state: &ConsoleState) -> Self {
+        let mut metrics = Self {
+            up: state.connection == ConnectionStatus::Live,
+            ..Default::default()
+        };
+
+        for task in state.tasks.values() {
+            let task_state = match task.state() {
+                TaskState::Running => "running",
+                TaskState::Idle => "idle",
+                TaskState::Completed => "completed",
+            };
+            let target = task.target.clone().unwrap_or_default();
+            *metrics.tasks.entry((task_state, target)).or_default() += 1;
+
+            if let Some(stats) = &task.stats {
+                metrics.polls += stats.polls;
+                metrics.busy += stats.busy_time.unwrap_or_default();
+            }
+        }
+
+        for resource in state.resources.values() {
+            *metrics.resources.entry(resource.kind.clone()).or_default() += 1;
+        }
+
+        metrics
+    }
+}
+
+fn render(consoles: &[(ConsoleAddr, ConsoleMetrics)]) -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "tokio_console_up",
+        "Whether the console is currently connected.",
+    );
+    for (addr, metrics) in consoles {
+        sample(
+            &mut out,
+            "tokio_console_up",
+            addr,
+            &[],
+            u8::from(metrics.up),
+        );
+    }
+
+    header(
+        &mut out,
+        "tokio_console_tasks",
+        "Number of known tasks by state and target.",
+    );
+    for (addr, metrics) in consoles {
+        for ((state, target), count) in &metrics.tasks {
+            let labels = [("state", *state), ("target", target.as_str())];
+            sample(&mut out, "tokio_console_tasks", addr, &labels, count);
+        }
+    }
+
+    header(
+        &mut out,
+        "tokio_console_known_task_polls",
+        "Number of times the currently known tasks have been polled. \
+         Tasks removed after the retention period no longer count, so this can go down.",
+    );
+    for (addr, metrics) in consoles {
+        sample(
+            &mut out,
+            "tokio_console_known_task_polls",
+            addr,
+            &[],
+            metrics.polls,
+        );
+    }
+
+    header(
+        &mut out,
+        "tokio_console_known_task_busy_seconds",
+        "Time the currently known tasks have spent being polled. \
+         Tasks removed after the retention period no longer count, so this can go down.",
+    );
+    for (addr, metrics) in consoles {
+        let busy = metrics.busy.as_secs_f64();
+        sample(
+            &mut out,
+            "tokio_console_known_task_busy_seconds",
+            addr,
+            &[],
+            busy,
+        );
+    }
+
+    header(
+        &mut out,
+        "tokio_console_resources",
+        "Number of known resources by kind.",
+    );
+    for (addr, metrics) in consoles {
+        for (kind, count) in &metrics.resources {
+            let labels = [("kind", kind.as_str())];
+            sample(&mut out, "tokio_console_resources", addr, &labels, count);
+        }
+    }
+
+    out
+}
+
+// tasks and resources are forgotten after the retention period so all of these can go down,
+// which makes them gauges rather than counters
+fn header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    addr: &ConsoleAddr,
+    labels: &[(&str, &str)],
+    value: impl std::fmt::Display,
+) {
+    let _ = write!(out, "{}{{console=\"{}\"", name, escape(&addr.to_string()));
+    for (key, value) in labels {
+        let _ = write!(out, ",{}=\"{}\"", key, escape(value));
+    }
+    let _ = writeln!(out, "}} {}", value);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watch_stream::{Resource, Task, TaskStats};
+    use std::sync::Arc;
+
+    fn task(id: u64, target: &str, polls: u64, busy: Duration) -> Arc<Task> {
+        let mut task = Task::try_from(console_api::tasks::Task {
+            id: Some(console_api::Id { id }),
+            metadata: Some(console_api::MetaId { id: 1 }),
+            location: Some(console_api::Location {
+                file: Some("src/main.rs".to_owned()),
+                module_path: None,
+                line: Some(1),
+                column: Some(1),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        task.target = Some(target.to_owned());
+        task.stats = Some(TaskStats {
+            dropped_at: None,
+            created_at: None,
+            busy_time: Some(busy),
+            last_poll_started: None,
+            last_poll_ended: None,
+            polls,
+            wakes: 0,
+            waker_clones: 0,
+            waker_drops: 0,
+            last_wake: None,
+            self_wakes: 0,
+        });
+        Arc::new(task)
+    }
+
+    fn resource(id: u64, kind: &str) -> Arc<Resource> {
+        let resource = Resource::try_from(console_api::resources::Resource {
+            id: Some(console_api::Id { id }),
+            metadata: Some(console_api::MetaId { id: 1 }),
+            kind: Some(console_api::resources::resource::Kind {
+                kind: Some(console_api::resources::resource::kind::Kind::Other(
+                    kind.to_owned(),
+                )),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        Arc::new(resource)
+    }
+
+    #[test]
+    fn exposition_text() {
+        let tasks = [
+            task(1, "app", 3, Duration::from_millis(500)),
+            task(2, "app", 4, Duration::from_millis(1000)),
+            task(3, "we\"ird\\tar\nget", 5, Duration::ZERO),
+        ];
+        let state = ConsoleState {
+            connection: ConnectionStatus::Live,
+            tasks: tasks
+                .iter()
+                .map(|task| (task.id, Arc::clone(task)))
+                .collect(),
+            resources: [resource(1, "Sleep")]
+                .into_iter()
+                .map(|resource| (resource.id, resource))
+                .collect(),
+            ..Default::default()
+        };
+        let addr = "127.0.0.1:6669".parse().unwrap();
+
+        let expected = r#"# HELP tokio_console_up Whether the console is currently connected.
+# TYPE tokio_console_up gauge
+tokio_console_up{console="127.0.0.1:6669"} 1
+# HELP tokio_console_tasks Number of known tasks by state and target.
+# TYPE tokio_console_tasks gauge
+tokio_console_tasks{console="127.0.0.1:6669",state="idle",target="app"} 2
+tokio_console_tasks{console="127.0.0.1:6669",state="idle",target="we\"ird\\tar\nget"} 1
+# HELP tokio_console_known_task_polls Number of times the currently known tasks have been polled. Tasks removed after the retention period no longer count, so this can go down.
+# TYPE tokio_console_known_task_polls gauge
+tokio_console_known_task_polls{console="127.0.0.1:6669"} 12
+# HELP tokio_console_known_task_busy_seconds Time the currently known tasks have spent being polled. Tasks removed after the retention period no longer count, so this can go down.
+# TYPE tokio_console_known_task_busy_seconds gauge
+tokio_console_known_task_busy_seconds{console="127.0.0.1:6669"} 1.5
+# HELP tokio_console_resources Number of known resources by kind.
+# TYPE tokio_console_resources gauge
+tokio_console_resources{console="127.0.0.1:6669",kind="Sleep"} 1
+"#;
+
+        assert_eq!(render(&[(addr, ConsoleMetrics::new(&state))]), expected);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{
+    console_addr::ConsoleAddr,
+    watch_stream::{ConnectionStatus, ConsoleStateWatch, RawUpdate},
+};
+use anyhow::Context as _;
+use parking_lot::Mutex;
+use prost::Message;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    sync::{
+        broadcast::{self, error::RecvError},
+        oneshot,
+    },
+    time::MissedTickBehavior,
+};
+
+/// A raw update received from the console along with the time it was received.
+///
+/// A recording is a file containing a sequence of length-delimited `Record`s.
+#[derive(Clone, PartialEq, Message)]
+pub struct Record {
+    #[prost(message, optional, tag = "1")]
+    pub recorded_at: Option<prost_types::Timestamp>,
+    #[prost(message, optional, tag = "2")]
+    pub update: Option<console_api::instrument::Update>,
+    /// The update contains everything the console knew about, rather than changes since the
+    /// previous record.
+    #[prost(bool, tag = "3")]
+    pub snapshot: bool,
+}
+
+pub const RECORDING_EXTENSION: &str = "console";
+
+/// How often buffered records are written to the recording file.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    active: Arc<Mutex<HashMap<ConsoleAddr, ActiveRecording>>>,
+}
+
+struct ActiveRecording {
+    path: PathBuf,
+    stop: oneshot::Sender<()>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            active: Default::default(),
+        }
+    }
+
+    pub fn is_recording(&self, addr: &ConsoleAddr) -> bool {
+        self.active.lock().contains_key(addr)
+    }
+
+    /// Start recording the updates received by `watch` to a new file.
+    ///
+    /// Returns the path of the recording. The recording keeps going until [`Recorder::stop`] is
+    /// called or the subscription ends. It doesn't keep the subscription open by itself.
+    pub async fn start(
+        &self,
+        addr: ConsoleAddr,
+        watch: &ConsoleStateWatch,
+    ) -> anyhow::Result<PathBuf> {
+        let existing = self
+            .active
+            .lock()
+            .get(&addr)
+            // a recording that has ended but not cleaned up yet can't be continued
+            .filter(|recording| !recording.stop.is_closed())
+            .map(|recording| recording.path.clone());
+        if let Some(path) = existing {
+            return Ok(path);
+        }
+
+        let updates = watch.raw_updates()?;
+
+        // the updates we receive only contain changes, so start from everything the console knows
+        // about unless we're still waiting for the first update
+        let snapshot = if watch.borrow().connection == ConnectionStatus::Live {
+            Some(watch.snapshot().await?)
+        } else {
+            None
+        };
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let (path, file) = create_recording_file(&self.dir, &addr).await?;
+
+        let (stop_tx, stop_rx) = oneshot::channel();
+        self.active.lock().insert(
+            addr.clone(),
+            ActiveRecording {
+                path: path.clone(),
+                stop: stop_tx,
+            },
+        );
+
+        let active = Arc::clone(&self.active);
+        let recording_path = path.clone();
+        tokio::spawn(async move {
+            tracing::debug!(?addr, path = ?recording_path, "recording started");
+            match write_recording(file, snapshot, updates, stop_rx).await {
+                Ok(()) => {
+                    tracing::debug!(?addr, path = ?recording_path, "recording ended");
+                }
+                Err(err) => {
+                    tracing::error!(%err, ?addr, path = ?recording_path, "recording failed");
+                }
+            }
+
+            let mut active = active.lock();
+            if active
+                .get(&addr)
+                .map_or(false, |recording| recording.path == recording_path)
+            {
+                active.remove(&addr);
+            }
+        });
+
+        Ok(path)
+    }
+
+    pub fn stop(&self, addr: &ConsoleAddr) -> Option<PathBuf> {
+        let recording = self.active.lock().remove(addr)?;
+        let _ = recording.stop.send(());
+        Some(recording.path)
+    }
+}
+
+/// Create a new file named after `addr` and the current time.
+async fn create_recording_file(dir: &Path, addr: &ConsoleAddr) -> anyhow::Result<(PathBuf, File)> {
+    let addr = addr
+        .to_string()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    // recordings of the same console could still be started in the same millisecond
+    let mut attempt = 0;
+    loop {
+        let name = match attempt {
+            0 => format!("{}-{}.{}", addr, timestamp, RECORDING_EXTENSION),
+            _ => format!("{}-{}-{}.{}", addr, timestamp, attempt, RECORDING_EXTENSION),
+        };
+        let path = dir.join(name);
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to create {}", path.display()))
+            }
+        }
+    }
+}
+
+async fn write_recording(
+    file: File,
+    snapshot: Option<console_api::instrument::Update>,
+    mut updates: broadcast::Receiver<Arc<RawUpdate>>,
+    mut stop: oneshot::Receiver<()>,
+) -> anyhow::Result<()> {
+    let mut file = RecordingFile::new(file);
+
+    // updates that were already included in the snapshot
+    let mut skip_until = None;
+    if let Some(snapshot) = snapshot {
+        skip_until = snapshot.now.clone().map(SystemTime::try_from).transpose()?;
+        file.write(snapshot, true).await?;
+    }
+
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let raw = tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) => update,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "recording fell behind, some updates were not recorded");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = flush.tick() => {
+                file.flush().await?;
+                continue;
+            }
+            _ = &mut stop => break,
+        };
+
+        if let Some(snapshot_time) = skip_until {
+            let time = raw
+                .update
+                .now
+                .clone()
+                .map(SystemTime::try_from)
+                .transpose()?;
+            if !raw.snapshot && time.map_or(true, |time| time <= snapshot_time) {
+                continue;
+            }
+            skip_until = None;
+        }
+
+        file.write(raw.update.clone(), raw.snapshot).await?;
+    }
+
+    file.flush().await?;
+
+    Ok(())
+}
+
+/// A recording being written, which buffers records and writes them out every
+/// [`FLUSH_INTERVAL`] rather than one at a time.
+struct RecordingFile {
+    /// Only `None` while being dropped.
+    file: Option<BufWriter<File>>,
+    buf: Vec<u8>,
+    unflushed: bool,
+}
+
+impl RecordingFile {
+    fn new(file: File) -> Self {
+        Self {
+            file: Some(BufWriter::new(file)),
+            buf: Vec::new(),
+            unflushed: false,
+        }
+    }
+
+    fn file(&mut self) -> &mut BufWriter<File> {
+        self.file.as_mut().expect("recording file already dropped")
+    }
+
+    async fn write(
+        &mut self,
+        update: console_api::instrument::Update,
+        snapshot: bool,
+    ) -> anyhow::Result<()> {
+        let record = Record {
+            recorded_at: Some(SystemTime::now().into()),
+            update: Some(update),
+            snapshot,
+        };
+
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        record.encode_length_delimited(&mut buf)?;
+        self.file().write_all(&buf).await?;
+        self.buf = buf;
+        self.unflushed = true;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if self.unflushed {
+            self.file().flush().await?;
+            self.unflushed = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RecordingFile {
+    fn drop(&mut self) {
+        // flushing is async, so write out whatever is still buffered synchronously, unless the
+        // file is in the middle of a write and can't be used
+        if let Some(file) = self.file.take() {
+            let buffered = file.buffer().to_vec();
+            if buffered.is_empty() {
+                return;
+            }
+            match file.into_inner().try_into_std() {
+                Ok(mut file) => {
+                    if let Err(err) = std::io::Write::write_all(&mut file, &buffered) {
+                        tracing::error!(%err, "failed to write the end of a recording");
+                    }
+                }
+                Err(_) => tracing::error!("recording dropped during a write, its end is lost"),
+            }
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    lints::Linter,
+    recording::{Record, RECORDING_EXTENSION},
+    watch_stream::{ConnectionStatus, ConsoleStateWatch, Retention, StatePipeline},
+};
+use anyhow::Context as _;
+use parking_lot::Mutex;
+use prost::Message;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    time::{sleep_until, Instant},
+};
+
+/// The speeds replays can be played at.
+pub const SPEEDS: [f64; 3] = [0.5, 1.0, 4.0];
+
+/// Recorded sessions that are currently being replayed, by name.
+#[derive(Clone)]
+pub struct Replays {
+    dir: PathBuf,
+    linter: Arc<Linter>,
+    retention: Retention,
+    inner: Arc<Mutex<HashMap<String, Replay>>>,
+}
+
+impl Replays {
+    pub fn new(dir: PathBuf, linter: Arc<Linter>, retention: Retention) -> Self {
+        Self {
+            dir,
+            linter,
+            retention,
+            inner: Default::default(),
+        }
+    }
+
+    /// The names of all recordings that can be replayed.
+    pub async fn list(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+            Err(err) => return Err(err.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(RECORDING_EXTENSION) {
+                continue;
+            }
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                names.push(name.to_owned());
+            }
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// Get the replay of the recording called `name`, loading it if necessary.
+    pub async fn open(&self, name: &str) -> anyhow::Result<Replay> {
+        if let Some(replay) = self.get(name) {
+            return Ok(replay);
+        }
+
+        let path = self.recording_path(name)?;
+        let records = read_recording(&path).await?;
+
+        let mut inner = self.inner.lock();
+        // someone else might have opened it while we were reading the file
+        if let Some(replay) = inner.get(name) {
+            return Ok(replay.clone());
+        }
+
+        let (mut pipeline, watch) =
+            StatePipeline::new(None, Arc::clone(&self.linter), self.retention);
+        pipeline.set_connection(ConnectionStatus::Replay)?;
+        let (commands_tx, commands_rx) = mpsc::channel(16);
+        let (status_tx, status_rx) = watch::channel(ReplayStatus::new(&records));
+
+        let map = Arc::clone(&self.inner);
+        let name = name.to_owned();
+        let replay = Replay {
+            watch,
+            commands: commands_tx,
+            status: status_rx,
+        };
+        inner.insert(name.clone(), replay.clone());
+
+        tokio::spawn(async move {
+            tracing::debug!(?name, "starting replay");
+            let mut player = Player {
+                name: name.clone(),
+                map: Arc::clone(&map),
+                records,
+                position: 0,
+                pipeline,
+                status: status_tx,
+                playing: true,
+                speed: 1.0,
+                deadline: None,
+            };
+            if let Err(err) = player.run(commands_rx).await {
+                tracing::error!(%err, ?name, "replay failed");
+                map.lock().remove(&name);
+            }
+            tracing::debug!(?name, "replay ended");
+        });
+
+        Ok(replay)
+    }
+
+    /// Get a replay that has already been opened.
+    pub fn get(&self, name: &str) -> Option<Replay> {
+        self.inner.lock().get(name).cloned()
+    }
+
+    fn recording_path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        // only allow plain file names so replays can't be used to read arbitrary files
+        let is_file_name = Path::new(name).file_name().and_then(|n| n.to_str()) == Some(name);
+        let has_extension =
+            Path::new(name).extension().and_then(|ext| ext.to_str()) == Some(RECORDING_EXTENSION);
+        anyhow::ensure!(is_file_name && has_extension, "Invalid recording name");
+
+        Ok(self.dir.join(name))
+    }
+}
+
+/// An update read from a recording.
+struct Recorded {
+    at: SystemTime,
+    update: console_api::instrument::Update,
+    /// See [`Record::snapshot`].
+    snapshot: bool,
+}
+
+async fn read_recording(path: &Path) -> anyhow::Result<Vec<Recorded>> {
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut buf = &data[..];
+    let mut records = Vec::new();
+    while !buf.is_empty() {
+        let Record {
+            recorded_at,
+            update,
+            snapshot,
+        } = Record::decode_length_delimited(&mut buf).context("Malformed recording")?;
+
+        let recorded_at = recorded_at.context("Missing `recorded_at` field")?;
+        let recorded_at = SystemTime::try_from(recorded_at)?;
+        let update = update.context("Missing `update` field")?;
+
+        records.push(Recorded {
+            at: recorded_at,
+            update,
+            snapshot,
+        });
+    }
+
+    Ok(records)
+}
+
+#[derive(Clone)]
+pub struct Replay {
+    pub watch: ConsoleStateWatch,
+    commands: mpsc::Sender<ReplayCommand>,
+    status: watch::Receiver<ReplayStatus>,
+}
+
+impl Replay {
+    pub fn status(&self) -> ReplayStatus {
+        self.status.borrow().clone()
+    }
+
+    pub async fn send(&self, command: ReplayCommand) -> anyhow::Result<()> {
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| anyhow::Error::msg("replay has ended"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayCommand {
+    Play,
+    Pause,
+    SetSpeed(f64),
+    /// Apply the next update and pause.
+    Step,
+    /// Jump to the given offset from the start of the recording and pause.
+    Seek(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayStatus {
+    pub position: usize,
+    pub len: usize,
+    pub playing: bool,
+    pub speed: f64,
+    pub start: Option<SystemTime>,
+    pub end: Option<SystemTime>,
+    pub current: Option<SystemTime>,
+}
+
+impl ReplayStatus {
+    fn new(records: &[Recorded]) -> Self {
+        Self {
+            position: 0,
+            len: records.len(),
+            playing: true,
+            speed: 1.0,
+            start: records.first().map(|record| record.at),
+            end: records.last().map(|record| record.at),
+            current: None,
+        }
+    }
+
+    /// How far into the recording we are.
+    pub fn offset(&self) -> Duration {
+        self.start
+            .zip(self.current)
+            .and_then(|(start, current)| current.duration_since(start).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.start
+            .zip(self.end)
+            .and_then(|(start, end)| end.duration_since(start).ok())
+            .unwrap_or_default()
+    }
+}
+
+struct Player {
+    name: String,
+    map: Arc<Mutex<HashMap<String, Replay>>>,
+    records: Vec<Recorded>,
+    /// The number of records that have been applied.
+    position: usize,
+    pipeline: StatePipeline,
+    status: watch::Sender<ReplayStatus>,
+    playing: bool,
+    speed: f64,
+    /// When to apply the next record, if we're playing.
+    deadline: Option<Instant>,
+}
+
+impl Player {
+    async fn run(&mut self, mut commands: mpsc::Receiver<ReplayCommand>) -> anyhow::Result<()> {
+        let check_receivers_freq = Duration::from_secs(10);
+        let mut check_receivers =
+            tokio::time::interval_at(Instant::now() + check_receivers_freq, check_receivers_freq);
+
+        self.schedule_next();
+
+        loop {
+            let deadline = self.deadline;
+
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => self.handle_command(command)?,
+                    None => break,
+                },
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    self.advance()?;
+                }
+                _ = check_receivers.tick() => {
+                    // hold the lock while checking so nobody can open the replay in the meantime
+                    let mut map = self.map.lock();
+
+                    // there will always be one receiver in the map
+                    // so if there is only 1, then all others are gone
+                    if self.pipeline.receiver_count() == 1 {
+                        map.remove(&self.name);
+                        break;
+                    }
+                }
+            }
+
+            self.publish_status();
+        }
+
+        Ok(())
+    }
+
+    fn handle_command(&mut self, command: ReplayCommand) -> anyhow::Result<()> {
+        match command {
+            ReplayCommand::Play => {
+                if self.position == self.records.len() {
+                    self.seek(Duration::ZERO)?;
+                }
+                self.playing = true;
+            }
+            ReplayCommand::Pause => {
+                self.playing = false;
+            }
+            ReplayCommand::SetSpeed(speed) => {
+                if SPEEDS.contains(&speed) {
+                    // keep the progress made towards the next record
+                    let now = Instant::now();
+                    if let Some(deadline) = &mut self.deadline {
+                        let remaining = deadline.saturating_duration_since(now);
+                        *deadline = now
+                            .checked_add(scale(remaining, self.speed / speed))
+                            .unwrap_or(*deadline);
+                    }
+                    self.speed = speed;
+                    return Ok(());
+                }
+            }
+            ReplayCommand::Step => {
+                self.playing = false;
+                self.advance()?;
+            }
+            ReplayCommand::Seek(offset) => {
+                self.playing = false;
+                self.seek(offset)?;
+            }
+        }
+
+        self.schedule_next();
+        Ok(())
+    }
+
+    /// Start waiting for the next record from now.
+    fn schedule_next(&mut self) {
+        self.deadline = self
+            .delay_until_next()
+            .and_then(|delay| Instant::now().checked_add(delay));
+    }
+
+    /// How long to wait before applying the next record, if we're playing.
+    fn delay_until_next(&self) -> Option<Duration> {
+        if !self.playing {
+            return None;
+        }
+
+        let next = self.records.get(self.position)?;
+        let delay = match self
+            .position
+            .checked_sub(1)
+            .and_then(|idx| self.records.get(idx))
+        {
+            Some(prev) => next.at.duration_since(prev.at).unwrap_or_default(),
+            None => Duration::ZERO,
+        };
+
+        Some(scale(delay, 1.0 / self.speed))
+    }
+
+    fn advance(&mut self) -> anyhow::Result<()> {
+        if let Some(record) = self.records.get(self.position) {
+            if record.snapshot {
+                // the console was reconnected to, and might have restarted in the meantime
+                self.pipeline.reset();
+            }
+            self.pipeline.push(record.update.clone())?;
+            self.position += 1;
+        }
+
+        if self.position == self.records.len() {
+            self.playing = false;
+        }
+
+        self.schedule_next();
+        Ok(())
+    }
+
+    fn seek(&mut self, offset: Duration) -> anyhow::Result<()> {
+        // seeking past the end goes to the last record
+        let target = self
+            .records
+            .first()
+            .zip(self.records.last())
+            .map(|(first, last)| first.at.checked_add(offset).unwrap_or(last.at).min(last.at));
+
+        self.pipeline.reset();
+        self.position = 0;
+
+        for record in &self.records {
+            if Some(record.at) > target {
+                break;
+            }
+            if record.snapshot {
+                self.pipeline.reset();
+            }
+            self.pipeline.apply(record.update.clone())?;
+            self.position += 1;
+        }
+
+        self.pipeline.publish()
+    }
+
+    fn publish_status(&self) {
+        let current = self
+            .position
+            .checked_sub(1)
+            .and_then(|idx| self.records.get(idx))
+            .map(|record| record.at);
+
+        let _ = self.status.send(ReplayStatus {
+            position: self.position,
+            len: self.records.len(),
+            playing: self.playing,
+            speed: self.speed,
+            start: self.records.first().map(|record| record.at),
+            end: self.records.last().map(|record| record.at),
+            current,
+        });
+    }
+}
+
+/// Multiply `duration` by `factor`, saturating rather than panicking if it doesn't fit.
+fn scale(duration: Duration, factor: f64) -> Duration {
+    let secs = duration.as_secs_f64() * factor;
+    if secs.is_nan() || secs <= 0.0 {
+        Duration::ZERO
+    } else if secs < Duration::MAX.as_secs_f64() {
+        Duration::from_secs_f64(secs)
+    } else {
+        Duration::MAX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_saturates() {
+        let second = Duration::from_secs(1);
+
+        assert_eq!(scale(second, 2.0), Duration::from_secs(2));
+        assert_eq!(scale(second, 1e300), Duration::MAX);
+        assert_eq!(scale(second, f64::INFINITY), Duration::MAX);
+        assert_eq!(scale(second, f64::NAN), Duration::ZERO);
+        assert_eq!(scale(second, -1.0), Duration::ZERO);
+        assert_eq!(scale(Duration::MAX, 2.0), Duration::MAX);
+    }
+}
@@ -1,30 +1,46 @@
+use crate::allowlist::Allowlist;
+use crate::config_file::{Targets, UiPreferences};
+use crate::console_addr::{self, ConsoleAddr};
+use crate::recording::Recorder;
+use crate::replay::{ReplayCommand, ReplayStatus, Replays, SPEEDS};
+use crate::tls::{TlsDir, TlsOptions};
 use crate::views::ConnectionFailed;
-use crate::watch_stream::ConsoleStateWatch;
+use crate::watch_stream::{ConnectionStatus, ConsoleState, ConsoleStateWatch};
 use crate::{
-    views::resources_index::ResourcesIndex, views::tasks_index::TasksIndex, views::Layout,
-    views::TaskResourceLayout, watch_stream::ConsoleSubscriptions,
+    views::async_ops_index::AsyncOpsIndex, views::resource_detail::ResourceDetail,
+    views::resources_index::ResourcesIndex, views::task_detail::TaskDetail,
+    views::tasks_index::TasksIndex, views::Layout, views::TaskResourceLayout,
+    watch_stream::ConsoleSubscriptions, watch_stream::ResourceId, watch_stream::TaskId,
 };
-use axum::extract::Extension;
+use axum::extract::{Extension, Form, FromRequest, RequestParts};
 use axum::handler::Handler;
 use axum::routing::MethodRouter;
 use axum::{
+    async_trait,
     extract::{Path, Query},
-    http::StatusCode,
-    response::{IntoResponse, Redirect},
-    routing::get,
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
     Router,
 };
 use axum_flash::Flash;
 use axum_live_view::{html, Html, LiveView, LiveViewUpgrade};
 use serde::Deserialize;
-use std::fmt;
+use std::{collections::HashMap, fmt, time::Duration};
 
 pub fn all() -> Router {
     Router::new()
         .merge(root())
         .merge(open_console())
         .merge(tasks_index())
+        .merge(task_detail())
         .merge(resources_index())
+        .merge(resource_detail())
+        .merge(async_ops_index())
+        .merge(disconnect())
+        .merge(toggle_recording())
+        .merge(replays_index())
+        .merge(replay_control())
         .fallback(fallback.into_service())
 }
 
@@ -32,6 +48,15 @@ fn route(path: &str, method_router: MethodRouter) -> Router {
     Router::new().route(path, method_router)
 }
 
+/// Route `page` for every kind of live console, see [`ConsoleAddr::path`].
+fn console_route(page: &str, method_router: MethodRouter) -> Router {
+    ConsoleAddr::route_patterns("/console", &format!("/{}", page))
+        .iter()
+        .fold(Router::new(), |router, pattern| {
+            router.route(pattern, method_router.clone())
+        })
+}
+
 async fn fallback(layout: Layout) -> (StatusCode, Html<()>) {
     let html = layout.render(html! {
         <p>"404 Not Found"</p>
@@ -40,24 +65,134 @@ async fn fallback(layout: Layout) -> (StatusCode, Html<()>) {
 }
 
 fn root() -> Router {
-    async fn handler(layout: Layout, params: Option<Query<ConsoleAddr>>) -> impl IntoResponse {
-        let Query(ConsoleAddr { ip, port }) = params.unwrap_or_default();
+    async fn handler(
+        layout: Layout,
+        params: Option<Query<OpenConsole>>,
+        Extension(subscriptions): Extension<ConsoleSubscriptions>,
+        Extension(targets): Extension<Targets>,
+    ) -> impl IntoResponse {
+        let Query(input) = params.unwrap_or_else(|| {
+            Query(OpenConsole {
+                addr: ConsoleAddr::default().to_string(),
+                ..Default::default()
+            })
+        });
+
+        let mut consoles = subscriptions
+            .active()
+            .await
+            .into_iter()
+            .map(|(addr, watch)| (addr, ConsoleSummary::new(&watch.borrow())))
+            .collect::<Vec<_>>();
+        consoles.sort_by_key(|(addr, _)| addr.to_string());
 
         layout.render::<()>(html! {
+            if !consoles.is_empty() {
+                <h2>"Consoles"</h2>
+
+                <table class="details-table">
+                    <tr>
+                        <th>"Console"</th>
+                        <th>"Status"</th>
+                        <th>"Tasks"</th>
+                        <th>"Resources"</th>
+                        <th>"Updates"</th>
+                        <th>"Uptime"</th>
+                        <th></th>
+                    </tr>
+                    for (addr, summary) in consoles {
+                        <tr>
+                            <td>{ addr.to_string() }</td>
+                            <td>{ summary.connection.to_string() }</td>
+                            <td>{ summary.tasks }</td>
+                            <td>{ summary.resources }</td>
+                            <td>
+                                if let Some(rate) = summary.update_rate {
+                                    { format!("{:.1}/s", rate) }
+                                }
+                            </td>
+                            <td>
+                                if let Some(uptime) = summary.uptime {
+                                    { format!("{:?}", uptime) }
+                                }
+                            </td>
+                            <td>
+                                <a href={ format!("/console/{}/tasks", addr.path()) }>"Tasks"</a>
+                                " | "
+                                <a href={ format!("/console/{}/resources", addr.path()) }>"Resources"</a>
+                                " | "
+                                <a href={ format!("/console/{}/async-ops", addr.path()) }>"Async ops"</a>
+                                <form
+                                    method="POST"
+                                    action={ format!("/console/{}/disconnect", addr.path()) }
+                                    class="inline-form"
+                                >
+                                    <input type="submit" value="Disconnect" />
+                                </form>
+                            </td>
+                        </tr>
+                    }
+                </table>
+
+                <h2>"Open console"</h2>
+            }
+
+            if targets.iter().next().is_some() {
+                <h2>"Targets"</h2>
+
+                <ul>
+                    for (name, target) in targets.iter() {
+                        <li>
+                            <a href={ target_path(name, "tasks") }>{ name }</a>
+                            " (" { target.addr.to_string() } ")"
+                        </li>
+                    }
+                </ul>
+            }
+
             <form method="GET" action="/open-console">
                 <div>
                     <label>
-                        <div>"IP"</div>
-                        <input type="text" name="ip" required focus value={ ip }/>
+                        <div>"Address"</div>
+                        <input
+                            type="text"
+                            name="addr"
+                            required
+                            focus
+                            placeholder="host:port, [::1]:port or unix:///path/to/socket"
+                            value={ &input.addr }
+                        />
                     </label>
                 </div>
 
-                <div>
-                    <label>
-                        <div>"Port"</div>
-                        <input type="text" name="port" required value={ port }/>
-                    </label>
-                </div>
+                <details>
+                    <summary>"TLS"</summary>
+                    <p>"Fill in any of these to connect over TLS. Files are names of PEM files in the server's TLS directory."</p>
+                    <div>
+                        <label>
+                            <div>"CA certificate"</div>
+                            <input type="text" name="ca_cert" value={ &input.ca_cert }/>
+                        </label>
+                    </div>
+                    <div>
+                        <label>
+                            <div>"Client certificate"</div>
+                            <input type="text" name="client_cert" value={ &input.client_cert }/>
+                        </label>
+                    </div>
+                    <div>
+                        <label>
+                            <div>"Client key"</div>
+                            <input type="text" name="client_key" value={ &input.client_key }/>
+                        </label>
+                    </div>
+                    <div>
+                        <label>
+                            <div>"Server name"</div>
+                            <input type="text" name="domain" value={ &input.domain }/>
+                        </label>
+                    </div>
+                </details>
 
                 <input type="submit" value="Go" />
             </form>
@@ -67,45 +202,121 @@ fn root() -> Router {
     route("/", get(handler))
 }
 
-#[derive(Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
-pub struct ConsoleAddr {
-    pub ip: String,
-    pub port: String,
+/// What the home page shows about a subscription.
+struct ConsoleSummary {
+    connection: ConnectionStatus,
+    tasks: usize,
+    resources: usize,
+    update_rate: Option<f64>,
+    uptime: Option<Duration>,
 }
 
-impl Default for ConsoleAddr {
-    fn default() -> Self {
+impl ConsoleSummary {
+    fn new(state: &ConsoleState) -> Self {
+        let uptime = state
+            .connected_at
+            .filter(|_| !state.connection.is_stale())
+            .and_then(|connected_at| connected_at.elapsed().ok())
+            // sub-second precision is just noise here
+            .map(|uptime| Duration::from_secs(uptime.as_secs()));
+
+        let update_rate = uptime
+            .filter(|uptime| !uptime.is_zero())
+            .map(|uptime| state.update_count as f64 / uptime.as_secs_f64());
+
         Self {
-            ip: "127.0.0.1".to_owned(),
-            port: "6669".to_owned(),
+            connection: state.connection.clone(),
+            tasks: state.tasks.len(),
+            resources: state.resources.len(),
+            update_rate,
+            uptime,
         }
     }
 }
 
-impl fmt::Display for ConsoleAddr {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{}", self.ip, self.port)
+#[derive(Deserialize, Default)]
+struct OpenConsole {
+    addr: String,
+    #[serde(default)]
+    ca_cert: String,
+    #[serde(default)]
+    client_cert: String,
+    #[serde(default)]
+    client_key: String,
+    #[serde(default)]
+    domain: String,
+}
+
+impl OpenConsole {
+    /// The TLS options, if any of them were filled in.
+    fn tls(&self, dir: &TlsDir) -> anyhow::Result<Option<TlsOptions>> {
+        fn non_empty(value: &str) -> Option<&str> {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value)
+        }
+
+        let file = |name: &str| non_empty(name).map(|name| dir.file(name)).transpose();
+
+        let tls = TlsOptions {
+            ca_cert: file(&self.ca_cert)?,
+            client_cert: file(&self.client_cert)?,
+            client_key: file(&self.client_key)?,
+            domain: non_empty(&self.domain).map(ToOwned::to_owned),
+        };
+        Ok((tls != TlsOptions::default()).then(|| tls))
+    }
+
+    /// The query string that fills in the form with these values again.
+    fn query(&self) -> String {
+        [
+            ("addr", &self.addr),
+            ("ca_cert", &self.ca_cert),
+            ("client_cert", &self.client_cert),
+            ("client_key", &self.client_key),
+            ("domain", &self.domain),
+        ]
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, console_addr::encode_segment(value)))
+        .collect::<Vec<_>>()
+        .join("&")
     }
 }
 
 fn open_console() -> Router {
+    async fn open(
+        input: &OpenConsole,
+        subscriptions: &ConsoleSubscriptions,
+        allowlist: &Allowlist,
+        tls_dir: &TlsDir,
+    ) -> anyhow::Result<ConsoleAddr> {
+        let addr = input.addr.trim().parse::<ConsoleAddr>()?;
+        allowlist.check(&addr).await?;
+
+        // leaving the TLS fields empty uses the options from the config file, if any
+        subscriptions
+            .subscribe_with_tls(addr.clone(), input.tls(tls_dir)?)
+            .await?
+            .connected()
+            .await?;
+        Ok(addr)
+    }
+
     async fn handler(
-        Query(addr): Query<ConsoleAddr>,
+        Query(input): Query<OpenConsole>,
         Extension(subscriptions): Extension<ConsoleSubscriptions>,
+        Extension(allowlist): Extension<Allowlist>,
+        Extension(tls_dir): Extension<TlsDir>,
         mut flash: Flash,
     ) -> impl IntoResponse {
-        match subscriptions.subscribe(addr.clone()).await {
-            Ok(_) => {
-                let uri = format!("/console/{}/{}/tasks", addr.ip, addr.port)
-                    .parse()
-                    .unwrap();
+        match open(&input, &subscriptions, &allowlist, &tls_dir).await {
+            Ok(addr) => {
+                let uri = format!("/console/{}/tasks", addr.path()).parse().unwrap();
                 Redirect::to(uri)
             }
             Err(err) => {
-                flash.error(format!("Failed to connect. Error: {}", err));
-                let uri = format!("/?ip={ip}&port={port}", ip = addr.ip, port = addr.port)
-                    .parse()
-                    .unwrap();
+                flash.error(format!("Failed to connect. Error: {:#}", err));
+                let uri = format!("/?{}", input.query()).parse().unwrap();
                 Redirect::to(uri)
             }
         }
@@ -115,36 +326,356 @@ fn open_console() -> Router {
 }
 
 fn tasks_index() -> Router {
-    route("/console/:ip/:port/tasks", get_state_view(TasksIndex::new))
+    state_route(
+        "tasks",
+        get_state_view(|source, state, preferences| {
+            TasksIndex::new(source, state).hide_completed(preferences.hide_completed)
+        }),
+    )
+}
+
+fn task_detail() -> Router {
+    state_route(
+        "tasks/:id",
+        get_entity_view(|source, state, id| TaskDetail::new(source, state, TaskId(id))),
+    )
 }
 
 fn resources_index() -> Router {
-    route(
-        "/console/:ip/:port/resources",
-        get_state_view(ResourcesIndex::new),
+    state_route(
+        "resources",
+        get_state_view(|source, state, _| ResourcesIndex::new(source, state)),
+    )
+}
+
+fn resource_detail() -> Router {
+    state_route(
+        "resources/:id",
+        get_entity_view(|source, state, id| ResourceDetail::new(source, state, ResourceId(id))),
+    )
+}
+
+fn async_ops_index() -> Router {
+    state_route(
+        "async-ops",
+        get_state_view(|source, state, _| AsyncOpsIndex::new(source, state)),
+    )
+}
+
+fn disconnect() -> Router {
+    async fn handler(
+        addr: ConsoleAddr,
+        Extension(subscriptions): Extension<ConsoleSubscriptions>,
+        mut flash: Flash,
+    ) -> impl IntoResponse {
+        if subscriptions.disconnect(&addr).await {
+            flash.info(format!("Disconnected from {}", addr));
+        } else {
+            flash.error(format!("Not connected to {}", addr));
+        }
+
+        Redirect::to("/".parse().unwrap())
+    }
+
+    console_route("disconnect", post(handler))
+}
+
+fn toggle_recording() -> Router {
+    async fn handler(
+        addr: ConsoleAddr,
+        Extension(subscriptions): Extension<ConsoleSubscriptions>,
+        Extension(recorder): Extension<Recorder>,
+        Extension(allowlist): Extension<Allowlist>,
+        mut flash: Flash,
+    ) -> impl IntoResponse {
+        if let Some(path) = recorder.stop(&addr) {
+            flash.info(format!("Saved recording to {}", path.display()));
+        } else {
+            let result = match allowlist.check(&addr).await {
+                Ok(()) => match subscriptions.subscribe(addr.clone()).await {
+                    Ok(state) => recorder.start(addr.clone(), &state).await,
+                    Err(err) => Err(err),
+                },
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(path) => {
+                    flash.info(format!("Recording to {}", path.display()));
+                }
+                Err(err) => {
+                    flash.error(format!("Failed to start recording. Error: {}", err));
+                }
+            }
+        }
+
+        let uri = format!("/console/{}/tasks", addr.path()).parse().unwrap();
+        Redirect::to(uri)
+    }
+
+    console_route("recording", post(handler))
+}
+
+fn replays_index() -> Router {
+    async fn handler(layout: Layout, Extension(replays): Extension<Replays>) -> impl IntoResponse {
+        let names = replays.list().await;
+
+        layout.render::<()>(html! {
+            <h2>"Recordings"</h2>
+
+            match names {
+                Ok(names) if names.is_empty() => {
+                    <p>"No recordings yet"</p>
+                }
+                Ok(names) => {
+                    <ul>
+                        for name in names {
+                            <li>
+                                <a href={ replay_path(&name, "tasks") }>{ name }</a>
+                            </li>
+                        }
+                    </ul>
+                }
+                Err(err) => {
+                    <p>"Failed to list recordings: " { err.to_string() }</p>
+                }
+            }
+        })
+    }
+
+    route("/replay", get(handler))
+}
+
+#[derive(Deserialize)]
+struct ReplayControl {
+    action: String,
+    speed: Option<f64>,
+    offset: Option<f64>,
+}
+
+impl ReplayControl {
+    /// The command to send to a replay with `status`, if the control is valid.
+    fn command(&self, status: &ReplayStatus) -> Option<ReplayCommand> {
+        match (self.action.as_str(), self.speed, self.offset) {
+            ("play", _, _) => Some(ReplayCommand::Play),
+            ("pause", _, _) => Some(ReplayCommand::Pause),
+            ("step", _, _) => Some(ReplayCommand::Step),
+            ("speed", Some(speed), _) if SPEEDS.contains(&speed) => {
+                Some(ReplayCommand::SetSpeed(speed))
+            }
+            ("seek", _, Some(offset)) if offset.is_finite() && offset >= 0.0 => {
+                let offset = offset.min(status.duration().as_secs_f64());
+                Some(ReplayCommand::Seek(Duration::from_secs_f64(offset)))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn replay_control() -> Router {
+    async fn handler(
+        Path(name): Path<String>,
+        Extension(replays): Extension<Replays>,
+        Form(control): Form<ReplayControl>,
+        mut flash: Flash,
+    ) -> impl IntoResponse {
+        let result = match replays.get(&name) {
+            Some(replay) => match control.command(&replay.status()) {
+                Some(command) => replay.send(command).await,
+                None => Err(anyhow::Error::msg("Invalid replay control")),
+            },
+            None => Err(anyhow::Error::msg("Replay is not running")),
+        };
+
+        if let Err(err) = result {
+            flash.error(err.to_string());
+        }
+
+        // the name is encoded, so this only fails if something is very wrong
+        match replay_path(&name, "tasks").parse() {
+            Ok(uri) => Redirect::to(uri),
+            Err(_) => Redirect::to(Uri::from_static("/replay")),
+        }
+    }
+
+    route("/replay/:name/control", post(handler))
+}
+
+/// Where the state shown by a view comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StateSource {
+    Console(ConsoleAddr),
+    /// A console named in the config file.
+    Target {
+        name: String,
+        addr: ConsoleAddr,
+    },
+    Replay(String),
+}
+
+impl StateSource {
+    /// The path of a page showing this source, such as `tasks` or `resources/1`.
+    pub fn path(&self, page: impl fmt::Display) -> String {
+        match self {
+            StateSource::Console(addr) => format!("/console/{}/{}", addr.path(), page),
+            StateSource::Target { name, .. } => target_path(name, page),
+            StateSource::Replay(name) => replay_path(name, page),
+        }
+    }
+
+    async fn watch(
+        &self,
+        subscriptions: &ConsoleSubscriptions,
+        replays: &Replays,
+    ) -> anyhow::Result<ConsoleStateWatch> {
+        match self {
+            StateSource::Console(addr) | StateSource::Target { addr, .. } => {
+                subscriptions.subscribe(addr.clone()).await
+            }
+            StateSource::Replay(name) => Ok(replays.open(name).await?.watch),
+        }
+    }
+}
+
+/// The path of a page showing the target called `name`.
+fn target_path(name: &str, page: impl fmt::Display) -> String {
+    format!(
+        "/console/by-name/{}/{}",
+        console_addr::encode_segment(name),
+        page
     )
 }
 
+/// The path of a page showing the replay of the recording called `name`.
+fn replay_path(name: &str, page: impl fmt::Display) -> String {
+    format!("/replay/{}/{}", console_addr::encode_segment(name), page)
+}
+
+impl fmt::Display for StateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateSource::Console(addr) => addr.fmt(f),
+            StateSource::Target { name, addr } => write!(f, "{} ({})", name, addr),
+            StateSource::Replay(name) => write!(f, "replay of {}", name),
+        }
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for StateSource
+where
+    B: Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Path(mut params) = Path::<HashMap<String, String>>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        if let Some(name) = params.remove("name") {
+            return Ok(StateSource::Replay(name));
+        }
+
+        if let Some(name) = params.remove("target") {
+            let Extension(targets) = Extension::<Targets>::from_request(req)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            return match targets.get(&name) {
+                Some(target) => Ok(StateSource::Target {
+                    addr: target.addr.clone(),
+                    name,
+                }),
+                None => Err(
+                    (StatusCode::NOT_FOUND, format!("No target named `{}`", name)).into_response(),
+                ),
+            };
+        }
+
+        let addr = ConsoleAddr::from_request(req).await?;
+
+        let Extension(allowlist) = Extension::<Allowlist>::from_request(req)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if let Err(err) = allowlist.check(&addr).await {
+            let mut flash = Flash::from_request(req)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            flash.error(err.to_string());
+            return Err(Redirect::to("/".parse().unwrap()).into_response());
+        }
+
+        Ok(StateSource::Console(addr))
+    }
+}
+
+/// Route `page` for both live consoles and replays.
+fn state_route(page: &str, method_router: MethodRouter) -> Router {
+    console_route(page, method_router.clone())
+        .route(
+            &format!("/console/by-name/:target/{}", page),
+            method_router.clone(),
+        )
+        .route(&format!("/replay/:name/{}", page), method_router)
+}
+
 fn get_state_view<B, F, L>(make_view: F) -> MethodRouter<B>
 where
     B: axum::body::HttpBody + Send + 'static,
     B::Data: Send,
     B::Error: Into<axum::BoxError>,
-    F: Fn(ConsoleAddr, ConsoleStateWatch) -> L + Clone + Send + 'static,
+    F: Fn(StateSource, ConsoleStateWatch, &UiPreferences) -> L + Clone + Send + 'static,
     L: LiveView,
 {
     get(
         |layout: TaskResourceLayout,
          live: LiveViewUpgrade,
          Extension(subscriptions): Extension<ConsoleSubscriptions>,
-         Path(addr): Path<ConsoleAddr>| async move {
-            match subscriptions.subscribe(addr.clone()).await {
+         Extension(replays): Extension<Replays>,
+         Extension(preferences): Extension<UiPreferences>,
+         source: StateSource| async move {
+            match source.watch(&subscriptions, &replays).await {
                 Ok(state) => Ok(live.response(|embed| {
-                    let view = make_view(addr, state);
+                    let view = make_view(source, state, &preferences);
                     layout.render(embed.embed(view))
                 })),
-                Err(err) => Err(live
-                    .response(|embed| layout.render(embed.embed(ConnectionFailed { addr, err })))),
+                Err(err) => Err(live.response(|embed| {
+                    layout.render(embed.embed(ConnectionFailed { source, err }))
+                })),
+            }
+        },
+    )
+}
+
+#[derive(Deserialize)]
+pub(crate) struct EntityPath {
+    pub(crate) id: u64,
+}
+
+fn get_entity_view<B, F, L>(make_view: F) -> MethodRouter<B>
+where
+    B: axum::body::HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<axum::BoxError>,
+    F: Fn(StateSource, ConsoleStateWatch, u64) -> L + Clone + Send + 'static,
+    L: LiveView,
+{
+    get(
+        |layout: TaskResourceLayout,
+         live: LiveViewUpgrade,
+         Extension(subscriptions): Extension<ConsoleSubscriptions>,
+         Extension(replays): Extension<Replays>,
+         source: StateSource,
+         Path(EntityPath { id }): Path<EntityPath>| async move {
+            match source.watch(&subscriptions, &replays).await {
+                Ok(state) => Ok(live.response(|embed| {
+                    let view = make_view(source, state, id);
+                    layout.render(embed.embed(view))
+                })),
+                Err(err) => Err(live.response(|embed| {
+                    layout.render(embed.embed(ConnectionFailed { source, err }))
+                })),
             }
         },
     )
@@ -0,0 +1,331 @@
+use anyhow::Context as _;
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
+
+/// How to connect to a console over TLS.
+///
+/// All paths are PEM files on the machine running the web UI.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsOptions {
+    /// CA bundle used to verify the console. The system roots are used if missing.
+    pub ca_cert: Option<PathBuf>,
+    /// Certificate presented to consoles that require client authentication.
+    pub client_cert: Option<PathBuf>,
+    /// Private key for `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// Server name to verify the console's certificate against, if it isn't the host we connect
+    /// to.
+    pub domain: Option<String>,
+}
+
+impl TlsOptions {
+    /// Resolve relative paths against `dir` rather than the working directory.
+    pub fn relative_to(&self, dir: &Path) -> Self {
+        // joining an absolute path replaces `dir`
+        let resolve = |path: &Option<PathBuf>| path.as_ref().map(|path| dir.join(path));
+
+        Self {
+            ca_cert: resolve(&self.ca_cert),
+            client_cert: resolve(&self.client_cert),
+            client_key: resolve(&self.client_key),
+            domain: self.domain.clone(),
+        }
+    }
+
+    pub fn client_config(&self) -> anyhow::Result<ClientTlsConfig> {
+        let mut config = ClientTlsConfig::new();
+
+        if let Some(path) = &self.ca_cert {
+            config = config.ca_certificate(Certificate::from_pem(read_pem(path)?));
+        }
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                config = config.identity(Identity::from_pem(read_pem(cert)?, read_pem(key)?));
+            }
+            (None, None) => {}
+            (Some(_), None) => anyhow::bail!("A client certificate requires a client key"),
+            (None, Some(_)) => anyhow::bail!("A client key requires a client certificate"),
+        }
+
+        if let Some(domain) = &self.domain {
+            config = config.domain_name(domain);
+        }
+
+        Ok(config)
+    }
+}
+
+/// The directory TLS files named in the connect form are read from, if any.
+///
+/// The form is public so it must not be able to read arbitrary files on the server.
+#[derive(Debug, Default, Clone)]
+pub struct TlsDir(pub Option<PathBuf>);
+
+impl TlsDir {
+    /// The path of the file called `name` in the directory.
+    pub fn file(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let dir = self
+            .0
+            .as_ref()
+            .context("TLS files can only be used here if the server has a TLS directory")?;
+
+        let is_plain = Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        anyhow::ensure!(
+            is_plain,
+            "TLS files must be names within the TLS directory, got `{}`",
+            name
+        );
+
+        Ok(dir.join(name))
+    }
+}
+
+fn read_pem(path: &Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console_addr::ConsoleAddr, watch_stream::ConsoleSubscriptions};
+    use console_api::instrument::{
+        instrument_server::{Instrument, InstrumentServer},
+        InstrumentRequest, PauseRequest, PauseResponse, ResumeRequest, ResumeResponse,
+        TaskDetailsRequest, Update,
+    };
+    use console_api::tasks::TaskDetails;
+    use std::{pin::Pin, time::Duration};
+    use tokio_stream::{wrappers::TcpListenerStream, Stream, StreamExt};
+    use tonic::{
+        transport::{Server, ServerTlsConfig},
+        Request, Response, Status,
+    };
+
+    type BoxStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+    /// A console that sends a single update without any tasks and then nothing.
+    struct QuietConsole;
+
+    #[tonic::async_trait]
+    impl Instrument for QuietConsole {
+        type WatchUpdatesStream = BoxStream<Update>;
+        type WatchTaskDetailsStream = BoxStream<TaskDetails>;
+
+        async fn watch_updates(
+            &self,
+            _request: Request<InstrumentRequest>,
+        ) -> Result<Response<Self::WatchUpdatesStream>, Status> {
+            // the subscription rejects updates without these
+            let update = Update {
+                task_update: Some(Default::default()),
+                resource_update: Some(Default::default()),
+                async_op_update: Some(Default::default()),
+                ..Default::default()
+            };
+            let stream = tokio_stream::once(Ok(update)).chain(tokio_stream::pending());
+            Ok(Response::new(Box::pin(stream)))
+        }
+
+        async fn watch_task_details(
+            &self,
+            _request: Request<TaskDetailsRequest>,
+        ) -> Result<Response<Self::WatchTaskDetailsStream>, Status> {
+            Err(Status::unimplemented("task details"))
+        }
+
+        async fn pause(
+            &self,
+            _request: Request<PauseRequest>,
+        ) -> Result<Response<PauseResponse>, Status> {
+            Err(Status::unimplemented("pause"))
+        }
+
+        async fn resume(
+            &self,
+            _request: Request<ResumeRequest>,
+        ) -> Result<Response<ResumeResponse>, Status> {
+            Err(Status::unimplemented("resume"))
+        }
+    }
+
+    /// A CA, plus a server and a client certificate signed by it, written to a temporary
+    /// directory.
+    struct Pki {
+        dir: PathBuf,
+        server_cert: String,
+        server_key: String,
+        ca_cert: String,
+    }
+
+    impl Pki {
+        fn generate() -> Self {
+            let dir = std::env::temp_dir().join(format!("tls-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+            ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+
+            let server = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+            let client = rcgen::generate_simple_self_signed(vec!["client".to_owned()]).unwrap();
+
+            let pki = Self {
+                server_cert: server.serialize_pem_with_signer(&ca).unwrap(),
+                server_key: server.serialize_private_key_pem(),
+                ca_cert: ca.serialize_pem().unwrap(),
+                dir,
+            };
+
+            std::fs::write(pki.path("ca.pem"), &pki.ca_cert).unwrap();
+            std::fs::write(
+                pki.path("client.pem"),
+                client.serialize_pem_with_signer(&ca).unwrap(),
+            )
+            .unwrap();
+            std::fs::write(pki.path("client.key"), client.serialize_private_key_pem()).unwrap();
+
+            // a CA the server's certificate isn't signed by
+            let mut other_params = rcgen::CertificateParams::new(Vec::new());
+            other_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+            let other_ca = rcgen::Certificate::from_params(other_params).unwrap();
+            std::fs::write(pki.path("other-ca.pem"), other_ca.serialize_pem().unwrap()).unwrap();
+
+            pki
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.join(name)
+        }
+
+        fn options(&self) -> TlsOptions {
+            TlsOptions {
+                ca_cert: Some(self.path("ca.pem")),
+                client_cert: Some(self.path("client.pem")),
+                client_key: Some(self.path("client.key")),
+                // the server certificate is for `localhost` but we connect to `127.0.0.1`
+                domain: Some("localhost".to_owned()),
+            }
+        }
+
+        /// Start a console that requires client certificates signed by our CA.
+        async fn serve(&self) -> ConsoleAddr {
+            let tls = ServerTlsConfig::new()
+                .identity(Identity::from_pem(&self.server_cert, &self.server_key))
+                .client_ca_root(Certificate::from_pem(&self.ca_cert));
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            let server = Server::builder()
+                .tls_config(tls)
+                .unwrap()
+                .add_service(InstrumentServer::new(QuietConsole))
+                .serve_with_incoming(TcpListenerStream::new(listener));
+            tokio::spawn(server);
+
+            ConsoleAddr::Tcp {
+                host: "127.0.0.1".to_owned(),
+                port,
+            }
+        }
+    }
+
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn connect(addr: ConsoleAddr, tls: TlsOptions) -> anyhow::Result<()> {
+        let subscriptions = ConsoleSubscriptions::default();
+        subscriptions.set_tls(addr.clone(), Some(tls));
+
+        let mut watch = subscriptions.subscribe(addr).await?;
+        tokio::time::timeout(Duration::from_secs(10), watch.connected())
+            .await
+            .context("Timed out")?
+    }
+
+    /// Check that connecting failed because of TLS, rather than because the console is broken.
+    fn assert_tls_error(err: anyhow::Error) {
+        let message = format!("{:#}", err).to_lowercase();
+        assert!(
+            ["certificate", "handshake", "alert"]
+                .iter()
+                .any(|needle| message.contains(needle)),
+            "not a TLS error: {}",
+            message
+        );
+    }
+
+    #[tokio::test]
+    async fn connects_with_client_certificate() {
+        let pki = Pki::generate();
+        let addr = pki.serve().await;
+
+        connect(addr, pki.options()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_without_client_certificate() {
+        let pki = Pki::generate();
+        let addr = pki.serve().await;
+
+        let options = TlsOptions {
+            client_cert: None,
+            client_key: None,
+            ..pki.options()
+        };
+        assert_tls_error(connect(addr, options).await.unwrap_err());
+    }
+
+    #[tokio::test]
+    async fn fails_with_unknown_ca() {
+        let pki = Pki::generate();
+        let addr = pki.serve().await;
+
+        let options = TlsOptions {
+            ca_cert: Some(pki.path("other-ca.pem")),
+            ..pki.options()
+        };
+        let err = connect(addr, options).await.unwrap_err();
+        assert!(
+            err.to_string().to_lowercase().contains("certificate"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn tls_dir_files() {
+        let dir = TlsDir(Some("/etc/console".into()));
+
+        assert_eq!(
+            dir.file("certs/ca.pem").unwrap(),
+            Path::new("/etc/console/certs/ca.pem")
+        );
+        for name in [
+            "/etc/passwd",
+            "../secret.pem",
+            "certs/../../secret.pem",
+            "./ca.pem",
+        ] {
+            assert!(dir.file(name).is_err(), "{}", name);
+        }
+        assert!(TlsDir(None).file("ca.pem").is_err());
+    }
+
+    #[test]
+    fn client_cert_requires_key() {
+        let options = TlsOptions {
+            client_cert: Some("client.pem".into()),
+            ..Default::default()
+        };
+        assert!(options.client_config().is_err());
+    }
+}
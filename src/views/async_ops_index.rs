@@ -0,0 +1,346 @@
+use std::sync::Arc;
+
+use super::{
+    filter::Filterable,
+    render_connection,
+    table::{Sort, TableView},
+    table_view_keybinds::{TableViewKeybinds, TableViewKeybindsUpdate},
+    StateRef,
+};
+use crate::{
+    routes::StateSource,
+    watch_stream::{AsyncOp, AsyncOpId, ConsoleState, ConsoleStateWatch, ResourceId, Viewer},
+};
+use axum::{
+    async_trait,
+    http::{HeaderMap, Uri},
+};
+use axum_live_view::{
+    event_data::EventData,
+    html,
+    js_command::{self, JsCommand},
+    live_view::{Updated, ViewHandle},
+    Html, LiveView,
+};
+use serde::{Deserialize, Serialize};
+
+pub struct AsyncOpsIndex {
+    rx: ConsoleStateWatch,
+    _viewer: Viewer,
+    paused_state: Option<ConsoleState>,
+    source: StateSource,
+    table_keybinds: TableViewKeybinds<AsyncOpId>,
+    sort: Option<Sort<Column>>,
+}
+
+impl AsyncOpsIndex {
+    pub fn new(source: StateSource, rx: ConsoleStateWatch) -> Self {
+        Self {
+            source,
+            _viewer: rx.viewer(),
+            rx,
+            paused_state: None,
+            table_keybinds: Default::default(),
+            sort: None,
+        }
+    }
+}
+
+impl AsyncOpsIndex {
+    fn navigate_to_resource_command(&self, id: ResourceId) -> JsCommand {
+        let uri = self
+            .source
+            .path(format!("resources/{}", id.0))
+            .parse()
+            .expect("invalid URI");
+        js_command::navigate_to(uri)
+    }
+
+    fn toggle_play_pause(&mut self) {
+        if self.paused_state.is_some() {
+            self.paused_state = None;
+        } else {
+            self.paused_state = Some(self.rx.borrow().clone());
+        }
+    }
+
+    async fn do_update(
+        mut self,
+        msg: Msg,
+        data: Option<EventData>,
+    ) -> Result<Updated<Self>, anyhow::Error> {
+        let mut commands = Vec::new();
+
+        match msg {
+            Msg::TogglePlayPause => {
+                self.toggle_play_pause();
+            }
+            Msg::Sort(column) => {
+                self.sort_by(column);
+            }
+            Msg::Key => match self.table_keybinds.update(data.as_ref()) {
+                Some(TableViewKeybindsUpdate::Selected(id)) => {
+                    let resource_id = self.state().async_ops.get(&id).map(|op| op.resource_id);
+                    if let Some(resource_id) = resource_id {
+                        commands.push(self.navigate_to_resource_command(resource_id));
+                    }
+                }
+                Some(TableViewKeybindsUpdate::Move(delta)) => {
+                    self.move_selection(delta);
+                }
+                Some(TableViewKeybindsUpdate::Page(delta)) => {
+                    self.move_page(delta);
+                }
+                Some(TableViewKeybindsUpdate::TogglePlayPause) => {
+                    self.toggle_play_pause();
+                }
+                Some(TableViewKeybindsUpdate::GotoTasks) => {
+                    commands.push(js_command::navigate_to(
+                        self.source.path("tasks").parse().unwrap(),
+                    ));
+                }
+                Some(TableViewKeybindsUpdate::GotoResources) => {
+                    commands.push(js_command::navigate_to(
+                        self.source.path("resources").parse().unwrap(),
+                    ));
+                }
+                Some(TableViewKeybindsUpdate::GotoAsyncOps) => {}
+                Some(TableViewKeybindsUpdate::Sort(change)) => {
+                    self.update_sort(change);
+                }
+                None => {}
+            },
+            Msg::RowClick(resource_id) => {
+                commands.push(self.navigate_to_resource_command(resource_id));
+            }
+            // rows are read from the latest state when rendering
+            Msg::Update => {}
+            Msg::Error => {
+                anyhow::bail!("console subscription disconnected")
+            }
+        }
+
+        self.sync_selection();
+
+        Ok(Updated::new(self).with_all(commands))
+    }
+}
+
+#[async_trait]
+impl LiveView for AsyncOpsIndex {
+    type Message = Msg;
+    type Error = anyhow::Error;
+
+    async fn mount(
+        &mut self,
+        _uri: Uri,
+        _request_headers: &HeaderMap,
+        handle: ViewHandle<Self::Message>,
+    ) -> Result<(), Self::Error> {
+        let mut rx = self.rx.clone();
+        tokio::spawn(async move {
+            loop {
+                if rx.changed().await.is_err() {
+                    break;
+                }
+                if handle.send(Msg::Update).await.is_err() {
+                    break;
+                }
+            }
+            let _ = handle.send(Msg::Error).await;
+        });
+        Ok(())
+    }
+
+    async fn update(
+        mut self,
+        msg: Self::Message,
+        data: Option<EventData>,
+    ) -> Result<Updated<Self>, Self::Error> {
+        self.do_update(msg, data).await
+    }
+
+    fn render(&self) -> Html<Self::Message> {
+        let connection = render_connection(&self.source, &self.rx.borrow());
+
+        html! {
+            { connection }
+
+            { self.table_keybinds.help() }
+
+            <div>
+                if self.paused_state.is_some() {
+                    <button axm-click={ Msg::TogglePlayPause }>"Play"</button>
+                } else {
+                    <button axm-click={ Msg::TogglePlayPause }>"Pause"</button>
+                }
+            </div>
+
+            { self.table_render() }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum Msg {
+    TogglePlayPause,
+    RowClick(ResourceId),
+    Sort(Column),
+    Key,
+    Update,
+    Error,
+}
+
+impl TableView for AsyncOpsIndex {
+    type Column = Column;
+    type Model = Arc<AsyncOp>;
+    type Id = AsyncOpId;
+    type Msg = Msg;
+
+    fn columns(&self) -> Vec<Self::Column> {
+        Column::all()
+    }
+
+    fn state(&self) -> StateRef<'_, ConsoleState> {
+        let state = self.rx.borrow();
+        if let Some(state) = &self.paused_state {
+            StateRef::Ref(state)
+        } else {
+            StateRef::BorrowedFromWatch(state)
+        }
+    }
+
+    fn rows(&self) -> Vec<Self::Model> {
+        self.state().async_ops.values().cloned().collect()
+    }
+
+    fn render_column(
+        &self,
+        col: &Self::Column,
+        row: &Self::Model,
+        state: &ConsoleState,
+    ) -> Html<Self::Msg> {
+        match col {
+            Column::ID => {
+                html! { { row.id.0 } }
+            }
+            Column::Parent => {
+                html! {
+                    if let Some(parent_id) = row.parent_id {
+                        { parent_id.0 }
+                    }
+                }
+            }
+            Column::Resource => {
+                html! { { row.resource_id.0 } }
+            }
+            Column::Task => {
+                html! {
+                    if let Some(task_id) = row.task_id() {
+                        { task_id.0 }
+                        if let Some(name) = state.tasks.get(&task_id).and_then(|task| task.name()) {
+                            " " <code>{ name }</code>
+                        }
+                    }
+                }
+            }
+            Column::Source => {
+                html! { <code>{ &row.source }</code> }
+            }
+            Column::Total | Column::Busy | Column::Idle => {
+                html! {
+                    if let Some(duration) = row.filter_value(self.column_key(col), state) {
+                        { duration.to_string() }
+                    }
+                }
+            }
+            Column::Polls => {
+                html! {
+                    if let Some(stats) = &row.stats {
+                        { stats.polls }
+                    }
+                }
+            }
+            Column::Target => {
+                html! {
+                    if let Some(target) = &row.target {
+                        <code>{ target }</code>
+                    }
+                }
+            }
+            Column::Attributes => {
+                html! {
+                    if let Some(stats) = &row.stats {
+                        for attribute in &stats.attributes {
+                            <code>{ attribute.to_string() }</code>
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn row_click_event(&self, row: &Self::Model) -> Self::Msg {
+        Msg::RowClick(row.resource_id)
+    }
+
+    fn key_event(&self) -> Self::Msg {
+        Msg::Key
+    }
+
+    fn row_id(&self, row: &Self::Model) -> AsyncOpId {
+        row.id
+    }
+
+    fn sort(&self) -> Option<Sort<Column>> {
+        self.sort
+    }
+
+    fn set_sort(&mut self, sort: Option<Sort<Column>>) {
+        self.sort = sort;
+    }
+
+    fn sort_event(&self, col: &Column) -> Self::Msg {
+        Msg::Sort(*col)
+    }
+
+    fn keybinds(&self) -> &TableViewKeybinds<AsyncOpId> {
+        &self.table_keybinds
+    }
+
+    fn keybinds_mut(&mut self) -> &mut TableViewKeybinds<AsyncOpId> {
+        &mut self.table_keybinds
+    }
+
+    fn column_key(&self, col: &Column) -> &'static str {
+        match col {
+            Column::ID => "id",
+            Column::Parent => "parent",
+            Column::Resource => "resource",
+            Column::Task => "task",
+            Column::Source => "source",
+            Column::Total => "total",
+            Column::Busy => "busy",
+            Column::Idle => "idle",
+            Column::Polls => "polls",
+            Column::Target => "target",
+            Column::Attributes => "attributes",
+        }
+    }
+}
+
+columns_enum! {
+    pub enum Column {
+        ID,
+        Parent,
+        Resource,
+        Task,
+        Source,
+        Total,
+        Busy,
+        Idle,
+        Polls,
+        Target,
+        Attributes,
+    }
+}
@@ -0,0 +1,606 @@
+//! Filters for table views.
+//!
+//! A filter is a list of whitespace separated terms that must all match. A term is either free
+//! text, which is searched for in the row's name, target, location and fields, or a structured
+//! term such as `state:idle`, `target:hyper::*`, `polls>1000`, `busy>=1.5s` or
+//! `field.peer=10.0.0.1`. Values containing whitespace can be quoted, as in `name:"my task"`, and a
+//! quoted term is always free text.
+
+use crate::watch_stream::{
+    AsyncOp, ConsoleState, FieldValue, Location, Resource, Task, TaskState, TypeVisibility,
+};
+use regex::Regex;
+use std::{fmt, sync::Arc, time::Duration};
+
+#[derive(Default)]
+pub(crate) struct Filter {
+    query: String,
+    terms: Vec<Term>,
+}
+
+enum Term {
+    /// Case insensitive substring search.
+    Text(String),
+    Compare {
+        key: String,
+        op: Op,
+        value: String,
+        pattern: Option<Regex>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    /// `key:pattern`, where `*` matches anything.
+    Matches,
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A value a row can be filtered and sorted by.
+///
+/// Values are ordered with numbers before text. Missing values are `None`, which sorts before
+/// everything else.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum FilterValue {
+    Number(u64),
+    /// Compared against durations with a unit, such as `250ms` or `1.5s`.
+    Duration(Duration),
+    Text(String),
+    /// Filtered as `file:line:column` text, but sorted by line and column numerically.
+    Location(String, u32, u32),
+}
+
+impl fmt::Display for FilterValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterValue::Number(n) => n.fmt(f),
+            FilterValue::Duration(duration) => write!(f, "{:?}", duration),
+            FilterValue::Text(s) => s.fmt(f),
+            FilterValue::Location(file, line, column) => {
+                write!(f, "{}:{}:{}", file, line, column)
+            }
+        }
+    }
+}
+
+impl From<u64> for FilterValue {
+    fn from(n: u64) -> Self {
+        FilterValue::Number(n)
+    }
+}
+
+impl From<usize> for FilterValue {
+    fn from(n: usize) -> Self {
+        FilterValue::Number(n as u64)
+    }
+}
+
+impl From<Duration> for FilterValue {
+    fn from(duration: Duration) -> Self {
+        FilterValue::Duration(duration)
+    }
+}
+
+impl From<String> for FilterValue {
+    fn from(s: String) -> Self {
+        FilterValue::Text(s)
+    }
+}
+
+impl From<&str> for FilterValue {
+    fn from(s: &str) -> Self {
+        FilterValue::Text(s.to_owned())
+    }
+}
+
+impl From<&Location> for FilterValue {
+    fn from(location: &Location) -> Self {
+        FilterValue::Location(location.file.clone(), location.line, location.column)
+    }
+}
+
+impl From<&FieldValue> for FilterValue {
+    fn from(value: &FieldValue) -> Self {
+        match value {
+            FieldValue::U64(n) => FilterValue::Number(*n),
+            other => FilterValue::Text(other.to_string()),
+        }
+    }
+}
+
+impl Filter {
+    pub(crate) fn parse(query: &str) -> Self {
+        let terms = split_terms(query)
+            .into_iter()
+            .map(|(term, quoted)| {
+                if quoted {
+                    Term::Text(term.to_lowercase())
+                } else {
+                    Term::parse(&term)
+                }
+            })
+            .collect();
+
+        Self {
+            query: query.to_owned(),
+            terms,
+        }
+    }
+
+    pub(crate) fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Check an entity from `state`.
+    pub(crate) fn matches_entity<T>(&self, entity: &T, state: &ConsoleState) -> bool
+    where
+        T: Filterable,
+    {
+        self.matches(&entity.filter_text(state), |key| {
+            entity.filter_value(key, state)
+        })
+    }
+
+    /// Check a row.
+    ///
+    /// `text` is what free text terms are searched in and `value` looks up values for structured
+    /// terms.
+    pub(crate) fn matches<F>(&self, text: &str, value: F) -> bool
+    where
+        F: Fn(&str) -> Option<FilterValue>,
+    {
+        let text = text.to_lowercase();
+
+        self.terms.iter().all(|term| match term {
+            Term::Text(needle) => text.contains(needle),
+            Term::Compare {
+                key,
+                op,
+                value: expected,
+                pattern,
+            } => match value(key) {
+                Some(actual) => compare(&actual, *op, expected, pattern.as_ref()),
+                None => false,
+            },
+        })
+    }
+}
+
+/// Something that can be filtered and sorted.
+///
+/// `state` is the state the entity is part of. Values that change over time, such as how long a
+/// task has been idle, are as of `state.now`.
+pub(crate) trait Filterable {
+    /// The text free text terms are searched in.
+    fn filter_text(&self, state: &ConsoleState) -> String;
+
+    /// The value structured terms such as `polls>1000` compare against.
+    fn filter_value(&self, key: &str, state: &ConsoleState) -> Option<FilterValue>;
+}
+
+impl<T> Filterable for Arc<T>
+where
+    T: Filterable,
+{
+    fn filter_text(&self, state: &ConsoleState) -> String {
+        T::filter_text(self, state)
+    }
+
+    fn filter_value(&self, key: &str, state: &ConsoleState) -> Option<FilterValue> {
+        T::filter_value(self, key, state)
+    }
+}
+
+impl Filterable for Task {
+    fn filter_text(&self, _state: &ConsoleState) -> String {
+        let mut text = format!(
+            "{} {} {}",
+            self.name().unwrap_or_default(),
+            self.target.as_deref().unwrap_or_default(),
+            self.location,
+        );
+        for (name, value) in &self.fields {
+            text.push_str(&format!(" {}={}", name, value));
+        }
+        text
+    }
+
+    fn filter_value(&self, key: &str, state: &ConsoleState) -> Option<FilterValue> {
+        let stats = self.stats.as_ref();
+
+        if let Some(field) = key.strip_prefix("field.") {
+            return self.fields.get(field).map(Into::into);
+        }
+
+        match key {
+            "id" => Some(self.id.0.into()),
+            "state" => Some(
+                match self.state() {
+                    TaskState::Running => "running",
+                    TaskState::Idle => "idle",
+                    TaskState::Completed => "completed",
+                }
+                .into(),
+            ),
+            "name" => self.name().map(Into::into),
+            "target" => self.target.as_deref().map(Into::into),
+            "location" => Some((&self.location).into()),
+            "fields" => Some(
+                self.fields
+                    .iter()
+                    .filter(|(name, _)| name != &"task.name")
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .into(),
+            ),
+            "polls" => stats.map(|s| s.polls.into()),
+            "wakes" => stats.map(|s| s.wakes.into()),
+            "wakers" => stats.map(|s| s.waker_count().into()),
+            "self_wakes" => stats.map(|s| s.self_wakes.into()),
+            "warnings" => Some(state.warnings.get(&self.id).map_or(0, Vec::len).into()),
+            "total" => state.elapsed_since(stats?.created_at?).map(Into::into),
+            "busy" => stats?.busy_time.map(Into::into),
+            "idle" => stats?.idle_time(state.now?).map(Into::into),
+            "last_wake" => state.elapsed_since(stats?.last_wake?).map(Into::into),
+            _ => None,
+        }
+    }
+}
+
+impl Filterable for Resource {
+    fn filter_text(&self, _state: &ConsoleState) -> String {
+        format!(
+            "{} {} {} {}",
+            self.kind,
+            self.concrete_type,
+            self.target.as_deref().unwrap_or_default(),
+            self.location
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        )
+    }
+
+    fn filter_value(&self, key: &str, state: &ConsoleState) -> Option<FilterValue> {
+        match key {
+            "id" => Some(self.id.0.into()),
+            "parent" => self.parent_id.map(|id| id.0.into()),
+            "kind" => Some(self.kind.as_str().into()),
+            "type" => Some(self.concrete_type.as_str().into()),
+            "target" => self.target.as_deref().map(Into::into),
+            "location" => self.location.as_ref().map(Into::into),
+            "vis" => Some(
+                match self.vis {
+                    TypeVisibility::Public => "public",
+                    TypeVisibility::Internal => "internal",
+                }
+                .into(),
+            ),
+            "total" => state
+                .elapsed_since(self.stats.as_ref()?.created_at?)
+                .map(Into::into),
+            _ => None,
+        }
+    }
+}
+
+impl Filterable for AsyncOp {
+    fn filter_text(&self, state: &ConsoleState) -> String {
+        let task_name = self
+            .task_id()
+            .and_then(|id| state.tasks.get(&id))
+            .and_then(|task| task.name());
+        let mut text = format!(
+            "{} {} {}",
+            self.source,
+            self.target.as_deref().unwrap_or_default(),
+            task_name.unwrap_or_default(),
+        );
+        if let Some(stats) = &self.stats {
+            for attribute in &stats.attributes {
+                text.push_str(&format!(" {}", attribute));
+            }
+        }
+        text
+    }
+
+    fn filter_value(&self, key: &str, state: &ConsoleState) -> Option<FilterValue> {
+        let stats = self.stats.as_ref();
+
+        match key {
+            "id" => Some(self.id.0.into()),
+            "parent" => self.parent_id.map(|id| id.0.into()),
+            "resource" => Some(self.resource_id.0.into()),
+            "task" => self.task_id().map(|id| id.0.into()),
+            "source" => Some(self.source.as_str().into()),
+            "polls" => stats.map(|s| s.polls.into()),
+            "target" => self.target.as_deref().map(Into::into),
+            "attributes" => stats.map(|s| {
+                s.attributes
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .into()
+            }),
+            "total" => state.elapsed_since(stats?.created_at?).map(Into::into),
+            "busy" => stats?.busy_time.map(Into::into),
+            "idle" => stats?.idle_time(state.now?).map(Into::into),
+            _ => None,
+        }
+    }
+}
+
+/// Split a query on whitespace outside of double quotes, removing the quotes. Also returns whether
+/// each term started with a quote.
+fn split_terms(query: &str) -> Vec<(String, bool)> {
+    let mut terms = Vec::new();
+    let mut term: Option<(String, bool)> = None;
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+            term.get_or_insert_with(|| (String::new(), true));
+        } else if c.is_whitespace() && !in_quotes {
+            terms.extend(term.take());
+        } else {
+            term.get_or_insert_with(|| (String::new(), false)).0.push(c);
+        }
+    }
+    terms.extend(term);
+
+    terms
+}
+
+impl Term {
+    fn parse(term: &str) -> Self {
+        let split_at = term.find(|c: char| matches!(c, ':' | '=' | '>' | '<'));
+
+        let (key, rest) = match split_at {
+            Some(idx) if idx > 0 => term.split_at(idx),
+            _ => return Term::Text(term.to_lowercase()),
+        };
+
+        let is_key = key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !is_key {
+            return Term::Text(term.to_lowercase());
+        }
+
+        let (op, value) = if let Some(value) = rest.strip_prefix(">=") {
+            (Op::Ge, value)
+        } else if let Some(value) = rest.strip_prefix("<=") {
+            (Op::Le, value)
+        } else if let Some(value) = rest.strip_prefix('>') {
+            (Op::Gt, value)
+        } else if let Some(value) = rest.strip_prefix('<') {
+            (Op::Lt, value)
+        } else if let Some(value) = rest.strip_prefix('=') {
+            (Op::Eq, value)
+        } else {
+            (Op::Matches, &rest[1..])
+        };
+
+        let pattern = if op == Op::Matches {
+            let pattern = value
+                .split('*')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".*");
+            Regex::new(&format!("(?i)^{}$", pattern)).ok()
+        } else {
+            None
+        };
+
+        Term::Compare {
+            key: key.to_owned(),
+            op,
+            value: value.to_owned(),
+            pattern,
+        }
+    }
+}
+
+fn compare(actual: &FilterValue, op: Op, expected: &str, pattern: Option<&Regex>) -> bool {
+    match (op, actual) {
+        (Op::Matches, FilterValue::Location(..)) => {
+            pattern.map_or(false, |pattern| pattern.is_match(&actual.to_string()))
+        }
+        (Op::Eq, FilterValue::Location(..)) => actual.to_string() == expected,
+        (Op::Matches, FilterValue::Text(actual)) => {
+            pattern.map_or(false, |pattern| pattern.is_match(actual))
+        }
+        (Op::Matches, FilterValue::Number(actual)) => {
+            pattern.map_or(false, |pattern| pattern.is_match(&actual.to_string()))
+        }
+        (Op::Matches, FilterValue::Duration(_)) => false,
+        (Op::Eq, FilterValue::Text(actual)) => actual == expected,
+        (op, FilterValue::Number(actual)) => match expected.parse::<u64>() {
+            Ok(expected) => compare_ord(actual, op, &expected),
+            Err(_) => false,
+        },
+        (op, FilterValue::Duration(actual)) => match parse_duration(expected) {
+            Some(expected) => compare_ord(actual, op, &expected),
+            None => false,
+        },
+        (_, FilterValue::Text(_) | FilterValue::Location(..)) => false,
+    }
+}
+
+fn compare_ord<T: PartialOrd>(actual: &T, op: Op, expected: &T) -> bool {
+    match op {
+        Op::Eq => actual == expected,
+        Op::Gt => actual > expected,
+        Op::Ge => actual >= expected,
+        Op::Lt => actual < expected,
+        Op::Le => actual <= expected,
+        Op::Matches => false,
+    }
+}
+
+/// Parse a duration such as `100ms` or `1.5s`. The unit is required.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let unit_at = s.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (number, unit) = s.split_at(unit_at);
+
+    let number = number.parse::<f64>().ok()?;
+    let nanos_per_unit = match unit {
+        "ns" => 1.0,
+        "us" | "µs" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        "m" => 60e9,
+        "h" => 60.0 * 60e9,
+        _ => return None,
+    };
+
+    let nanos = number * nanos_per_unit;
+    if nanos.is_finite() && nanos < u64::MAX as f64 {
+        Some(Duration::from_nanos(nanos.round() as u64))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row with some text and values to filter.
+    fn matches(query: &str) -> bool {
+        let text = "my-task hyper::proto src/main.rs:10:5 peer=10.0.0.1";
+        let value = |key: &str| match key {
+            "id" => Some(FilterValue::from(42_u64)),
+            "state" => Some("idle".into()),
+            "target" => Some("hyper::proto".into()),
+            "name" => Some("my task".into()),
+            "polls" => Some(1000_u64.into()),
+            "busy" => Some(Duration::from_millis(1500).into()),
+            "field.peer" => Some("10.0.0.1".into()),
+            "location" => Some(FilterValue::Location("src/main.rs".to_owned(), 10, 5)),
+            _ => None,
+        };
+
+        Filter::parse(query).matches(text, value)
+    }
+
+    #[test]
+    fn queries() {
+        let cases = [
+            // free text
+            ("", true),
+            ("my-task", true),
+            ("MY-TASK", true),
+            ("main.rs hyper", true),
+            ("other", false),
+            ("my-task other", false),
+            // key:pattern
+            ("state:idle", true),
+            ("state:IDLE", true),
+            ("state:running", false),
+            ("target:hyper::*", true),
+            ("target:*proto", true),
+            ("target:hyper", false),
+            ("id:4*", true),
+            ("field.peer=10.0.0.1", true),
+            ("field.peer=10.0.0.2", false),
+            // numbers
+            ("polls=1000", true),
+            ("polls>999", true),
+            ("polls>1000", false),
+            ("polls>=1000", true),
+            ("polls<1000", false),
+            ("polls<1001", true),
+            ("polls<=1000", true),
+            ("polls<=999", false),
+            ("polls>lots", false),
+            // durations
+            ("busy>1s", true),
+            ("busy>1.5s", false),
+            ("busy>=1.5s", true),
+            ("busy<2s", true),
+            ("busy<=1499ms", false),
+            ("busy=1500ms", true),
+            ("busy>1m", false),
+            ("busy<1h", true),
+            ("busy>1", false),
+            ("busy>1parsec", false),
+            // locations
+            ("location:src/main.rs*", true),
+            ("location:*:10:*", true),
+            ("location=src/main.rs:10:5", true),
+            ("location=src/main.rs:10", false),
+            ("location>src", false),
+            // quoting
+            ("name:\"my task\"", true),
+            ("name:\"my\"", false),
+            ("\"my-task hyper\"", true),
+            ("\"hyper my-task\"", false),
+            ("\"my-task\"", true),
+            ("\"state:idle\"", false),
+            // comparisons don't apply to text
+            ("state>idle", false),
+            // unknown keys never match
+            ("unknown:*", false),
+            ("unknown>0", false),
+            ("main.rs:10", false),
+            // anything that can't be a key is searched for as text
+            ("src/main.rs:10", true),
+            ("::proto", true),
+        ];
+
+        for (query, expected) in cases {
+            assert_eq!(matches(query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn invalid_queries_dont_panic() {
+        for query in [
+            "polls>",
+            "polls>=",
+            "state:",
+            "\"",
+            "name:\"unterminated",
+            "busy>99999999999999999999h",
+            "busy>1.2.3s",
+            "busy>.s",
+            "target:[",
+            "target:(*",
+            "é:é",
+            "polls>>5",
+            "<=5",
+        ] {
+            matches(query);
+        }
+    }
+
+    #[test]
+    fn values_order_numbers_before_text_and_locations() {
+        let values = [
+            FilterValue::from(10_u64),
+            FilterValue::from(u64::MAX),
+            FilterValue::from(Duration::ZERO),
+            FilterValue::from(Duration::from_secs(1)),
+            FilterValue::from("0"),
+            FilterValue::from("a"),
+            FilterValue::Location("src/main.rs".to_owned(), 9, 1),
+            FilterValue::Location("src/main.rs".to_owned(), 10, 1),
+        ];
+
+        for pair in values.windows(2) {
+            assert!(pair[0] < pair[1], "{:?}", pair);
+        }
+    }
+}
@@ -1,5 +1,11 @@
-use crate::routes::ConsoleAddr;
-use axum::extract::Path;
+use super::render_time;
+use crate::{
+    console_addr::ConsoleAddr,
+    recording::Recorder,
+    replay::{ReplayStatus, Replays, SPEEDS},
+    routes::StateSource,
+};
+use axum::extract::Extension;
 use axum_flash::IncomingFlashes;
 use axum_live_view::{html, Html};
 
@@ -40,9 +46,47 @@ impl Layout {
                                 background: #ccc;
                             }
 
+                            table.resources-table th[axm-click] {
+                                cursor: pointer;
+                                text-align: left;
+                            }
+
+                            table.details-table {
+                                width: auto;
+                            }
+
+                            table.details-table th {
+                                text-align: left;
+                                padding-right: 1em;
+                            }
+
                             .keybinds {
                                 margin: 0.5em 0;
                             }
+
+                            .replay-controls {
+                                margin: 0.5em 0;
+                            }
+
+                            .replay-controls form {
+                                display: inline;
+                                margin-right: 0.5em;
+                            }
+
+                            .replay-controls input[type=number] {
+                                width: 6em;
+                            }
+
+                            form.inline-form {
+                                display: inline;
+                                margin-left: 0.5em;
+                            }
+
+                            .remote-paused {
+                                margin: 0.5em 0;
+                                padding: 0.5em;
+                                background: #fe9;
+                            }
                         "#
                     </style>
                 </head>
@@ -55,6 +99,8 @@ impl Layout {
 
                     <nav>
                         <a href="/">"Home"</a>
+                        " | "
+                        <a href="/replay">"Replays"</a>
                     </nav>
 
                     <hr />
@@ -74,20 +120,110 @@ impl Layout {
 #[from_request(rejection_derive(!Debug, !Display, !Error))]
 pub struct TaskResourceLayout {
     layout: Layout,
-    #[from_request(via(Path))]
-    addr: ConsoleAddr,
+    source: StateSource,
+    #[from_request(via(Extension))]
+    recorder: Recorder,
+    #[from_request(via(Extension))]
+    replays: Replays,
 }
 
 impl TaskResourceLayout {
     pub fn render<T>(self, content: Html<T>) -> Html<T> {
+        let controls = match &self.source {
+            StateSource::Console(addr) | StateSource::Target { addr, .. } => {
+                self.recording_controls(addr)
+            }
+            StateSource::Replay(name) => {
+                let status = self.replays.get(name).map(|replay| replay.status());
+                replay_controls(&self.source.path("control"), status)
+            }
+        };
+
         self.layout.render(html! {
             <nav>
-                <a href={ format!("/console/{}/{}/tasks", self.addr.ip, self.addr.port) }>"Tasks"</a>
+                <a href={ self.source.path("tasks") }>"Tasks"</a>
+                " | "
+                <a href={ self.source.path("resources") }>"Resources"</a>
                 " | "
-                <a href={ format!("/console/{}/{}/resources", self.addr.ip, self.addr.port) }>"Resources"</a>
+                <a href={ self.source.path("async-ops") }>"Async Ops"</a>
             </nav>
 
+            { controls }
+
             { content }
         })
     }
+
+    fn recording_controls<T>(&self, addr: &ConsoleAddr) -> Html<T> {
+        html! {
+            <form method="POST" action={ format!("/console/{}/recording", addr.path()) }>
+                if self.recorder.is_recording(addr) {
+                    "⏺ Recording "
+                    <input type="submit" value="Stop recording" />
+                } else {
+                    <input type="submit" value="Record" />
+                }
+            </form>
+        }
+    }
+}
+
+fn replay_controls<T>(action: &str, status: Option<ReplayStatus>) -> Html<T> {
+    html! {
+        if let Some(status) = status {
+            <div class="replay-controls">
+                <form method="POST" action={ action }>
+                    if status.playing {
+                        <input type="hidden" name="action" value="pause" />
+                        <input type="submit" value="Pause" />
+                    } else {
+                        <input type="hidden" name="action" value="play" />
+                        <input type="submit" value="Play" />
+                    }
+                </form>
+
+                <form method="POST" action={ action }>
+                    <input type="hidden" name="action" value="step" />
+                    <input type="submit" value="Step" />
+                </form>
+
+                "Speed: "
+                for speed in SPEEDS {
+                    <form method="POST" action={ action }>
+                        <input type="hidden" name="action" value="speed" />
+                        <input type="hidden" name="speed" value={ speed.to_string() } />
+                        if status.speed == speed {
+                            <input type="submit" value={ format!("{}x", speed) } disabled />
+                        } else {
+                            <input type="submit" value={ format!("{}x", speed) } />
+                        }
+                    </form>
+                }
+
+                <form method="POST" action={ action }>
+                    <input type="hidden" name="action" value="seek" />
+                    "Seek to "
+                    <input
+                        type="number"
+                        name="offset"
+                        min="0"
+                        max={ format!("{:.1}", status.duration().as_secs_f64()) }
+                        step="0.1"
+                        value={ format!("{:.1}", status.offset().as_secs_f64()) }
+                    />
+                    "s of " { format!("{:.1}", status.duration().as_secs_f64()) } "s "
+                    <input type="submit" value="Seek" />
+                </form>
+            </div>
+
+            <div>
+                "Update " { status.position } " of " { status.len }
+                if let Some(current) = status.current {
+                    ", recorded at " { render_time(current) }
+                }
+            </div>
+        } else {
+            <p>"This replay has ended. Reload the page to start it again."</p>
+        }
+    }
 }
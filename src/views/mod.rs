@@ -1,6 +1,9 @@
-use std::ops::Deref;
+use std::{ops::Deref, time::SystemTime};
 
-use crate::{routes::ConsoleAddr, watch_stream::Location};
+use crate::{
+    routes::StateSource,
+    watch_stream::{ConnectionStatus, ConsoleState, Location},
+};
 use axum::{
     async_trait,
     http::{HeaderMap, Uri},
@@ -12,9 +15,13 @@ use axum_live_view::{
     Html, LiveView,
 };
 
+pub mod async_ops_index;
+pub mod resource_detail;
 pub mod resources_index;
+pub mod task_detail;
 pub mod tasks_index;
 
+pub(crate) mod filter;
 mod layout;
 mod table;
 mod table_view_keybinds;
@@ -29,8 +36,37 @@ impl Location {
     }
 }
 
+fn render_time<T>(time: SystemTime) -> Html<T> {
+    let time = chrono::DateTime::<chrono::Utc>::from(time);
+    html! {
+        { time.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string() }
+    }
+}
+
+fn render_connection<T>(source: &StateSource, state: &ConsoleState) -> Html<T> {
+    let connection = &state.connection;
+    html! {
+        <div>
+            "Connection: " { source.to_string() }
+            if *connection != ConnectionStatus::Replay {
+                " (" { connection.to_string() } ")"
+            }
+            if connection.is_stale() {
+                " - showing last known state"
+            }
+        </div>
+
+        if state.remote_paused {
+            <div class="remote-paused">
+                "⏸ The console is paused for everyone watching it. "
+                "It resumes when nobody is viewing it anymore."
+            </div>
+        }
+    }
+}
+
 pub struct ConnectionFailed {
-    pub addr: ConsoleAddr,
+    pub source: StateSource,
     pub err: anyhow::Error,
 }
 
@@ -59,13 +95,13 @@ impl LiveView for ConnectionFailed {
     fn render(&self) -> Html<Self::Message> {
         html! {
             <div>
-                "Connection failed: " { &self.err }
+                "Connection failed: " { format!("{:#}", self.err) }
             </div>
         }
     }
 }
 
-enum StateRef<'a, T> {
+pub(crate) enum StateRef<'a, T> {
     BorrowedFromWatch(tokio::sync::watch::Ref<'a, T>),
     Ref(&'a T),
 }
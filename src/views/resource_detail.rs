@@ -0,0 +1,270 @@
+use super::{render_connection, render_time};
+use crate::{
+    routes::StateSource,
+    watch_stream::{ConsoleState, ConsoleStateWatch, Resource, ResourceId, TypeVisibility, Viewer},
+};
+use axum::{
+    async_trait,
+    http::{HeaderMap, Uri},
+};
+use axum_live_view::{
+    event_data::EventData,
+    html,
+    live_view::{Updated, ViewHandle},
+    Html, LiveView,
+};
+use serde::{Deserialize, Serialize};
+
+pub struct ResourceDetail {
+    rx: ConsoleStateWatch,
+    _viewer: Viewer,
+    source: StateSource,
+    id: ResourceId,
+}
+
+impl ResourceDetail {
+    pub fn new(source: StateSource, rx: ConsoleStateWatch, id: ResourceId) -> Self {
+        Self {
+            _viewer: rx.viewer(),
+            rx,
+            source,
+            id,
+        }
+    }
+}
+
+#[async_trait]
+impl LiveView for ResourceDetail {
+    type Message = Msg;
+    type Error = anyhow::Error;
+
+    async fn mount(
+        &mut self,
+        _uri: Uri,
+        _request_headers: &HeaderMap,
+        handle: ViewHandle<Self::Message>,
+    ) -> Result<(), Self::Error> {
+        let mut rx = self.rx.clone();
+        tokio::spawn(async move {
+            loop {
+                if rx.changed().await.is_err() {
+                    break;
+                }
+                if handle.send(Msg::Update).await.is_err() {
+                    break;
+                }
+            }
+            let _ = handle.send(Msg::Error).await;
+        });
+        Ok(())
+    }
+
+    async fn update(
+        mut self,
+        msg: Self::Message,
+        _data: Option<EventData>,
+    ) -> Result<Updated<Self>, Self::Error> {
+        match msg {
+            Msg::Update => {}
+            Msg::Error => {
+                anyhow::bail!("console subscription disconnected")
+            }
+        }
+
+        Ok(Updated::new(self))
+    }
+
+    fn render(&self) -> Html<Self::Message> {
+        let state = self.rx.borrow();
+
+        html! {
+            { render_connection(&self.source, &state) }
+
+            if let Some(resource) = state.resources.get(&self.id) {
+                { self.render_resource(&state, resource) }
+            } else {
+                <p>
+                    "Resource " { self.id.0 } " no longer exists."
+                </p>
+                <a href={ self.source.path("resources") }>
+                    "Back to resources"
+                </a>
+            }
+        }
+    }
+}
+
+impl ResourceDetail {
+    fn resource_uri(&self, id: ResourceId) -> String {
+        self.source.path(format!("resources/{}", id.0))
+    }
+
+    fn render_resource(&self, state: &ConsoleState, resource: &Resource) -> Html<Msg> {
+        let children = state
+            .resources
+            .values()
+            .filter(|child| child.parent_id == Some(resource.id))
+            .collect::<Vec<_>>();
+
+        let waiting = state
+            .poll_ops
+            .values()
+            .filter(|poll_op| poll_op.resource_id == resource.id && !poll_op.is_ready)
+            .collect::<Vec<_>>();
+
+        html! {
+            <h2>
+                "Resource " { resource.id.0 } " " <code>{ &resource.concrete_type }</code>
+            </h2>
+
+            <table class="details-table">
+                <tr>
+                    <th>"Kind"</th>
+                    <td>{ &resource.kind }</td>
+                </tr>
+                <tr>
+                    <th>"Type"</th>
+                    <td><code>{ &resource.concrete_type }</code></td>
+                </tr>
+                <tr>
+                    <th>"Visibility"</th>
+                    <td>
+                        match resource.vis {
+                            TypeVisibility::Public => "✅ public",
+                            TypeVisibility::Internal => "🔒 internal",
+                        }
+                    </td>
+                </tr>
+                <tr>
+                    <th>"Target"</th>
+                    <td>
+                        if let Some(target) = &resource.target {
+                            <code>{ target }</code>
+                        }
+                    </td>
+                </tr>
+                <tr>
+                    <th>"Location"</th>
+                    <td>
+                        if let Some(location) = &resource.location {
+                            <code>{ location.render() }</code>
+                        } else {
+                            "{unknown location}"
+                        }
+                    </td>
+                </tr>
+                <tr>
+                    <th>"Parent"</th>
+                    <td>
+                        if let Some(parent_id) = resource.parent_id {
+                            <a href={ self.resource_uri(parent_id) }>{ parent_id.0 }</a>
+                        }
+                    </td>
+                </tr>
+                if let Some(stats) = &resource.stats {
+                    <tr>
+                        <th>"Created at"</th>
+                        <td>
+                            if let Some(created_at) = stats.created_at {
+                                { render_time(created_at) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Dropped at"</th>
+                        <td>
+                            if let Some(dropped_at) = stats.dropped_at {
+                                { render_time(dropped_at) }
+                            }
+                        </td>
+                    </tr>
+                }
+            </table>
+
+            <h3>"Attributes"</h3>
+
+            if let Some(stats) = resource.stats.as_ref().filter(|stats| !stats.attributes.is_empty()) {
+                <table class="details-table">
+                    for attribute in &stats.attributes {
+                        <tr>
+                            <th><code>{ &attribute.name }</code></th>
+                            <td>
+                                <code>
+                                    { attribute.value.to_string() }
+                                    if let Some(unit) = &attribute.unit {
+                                        { unit }
+                                    }
+                                </code>
+                            </td>
+                        </tr>
+                    }
+                </table>
+            } else {
+                <p>"No attributes"</p>
+            }
+
+            <h3>"Child resources"</h3>
+
+            if children.is_empty() {
+                <p>"No child resources"</p>
+            } else {
+                <table class="resources-table">
+                    <thead>
+                        <tr>
+                            <th>"ID"</th>
+                            <th>"Kind"</th>
+                            <th>"Type"</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        for child in children {
+                            <tr>
+                                <td><a href={ self.resource_uri(child.id) }>{ child.id.0 }</a></td>
+                                <td>{ &child.kind }</td>
+                                <td><code>{ &child.concrete_type }</code></td>
+                            </tr>
+                        }
+                    </tbody>
+                </table>
+            }
+
+            <h3>"Waiting"</h3>
+
+            if waiting.is_empty() {
+                <p>"Nothing is waiting on this resource"</p>
+            } else {
+                <table class="resources-table">
+                    <thead>
+                        <tr>
+                            <th>"Async op"</th>
+                            <th>"Operation"</th>
+                            <th>"Task"</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        for poll_op in waiting {
+                            <tr>
+                                <td>{ poll_op.async_op_id.0 }</td>
+                                <td><code>{ &poll_op.name }</code></td>
+                                <td>
+                                    <a href={ self.source.path(format!("tasks/{}", poll_op.task_id.0)) }>
+                                        { poll_op.task_id.0 }
+                                        if let Some(name) = state.tasks.get(&poll_op.task_id).and_then(|task| task.name()) {
+                                            " " <code>{ name }</code>
+                                        }
+                                    </a>
+                                </td>
+                            </tr>
+                        }
+                    </tbody>
+                </table>
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum Msg {
+    Update,
+    Error,
+}
@@ -1,13 +1,18 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use super::{
-    table::TableView,
+    filter::Filterable,
+    render_connection,
+    table::{Sort, TableView},
     table_view_keybinds::{TableViewKeybinds, TableViewKeybindsUpdate},
     StateRef,
 };
 use crate::{
-    routes::ConsoleAddr,
-    watch_stream::{ConsoleState, ConsoleStateWatch, Resource, ResourceId, TypeVisibility},
+    routes::StateSource,
+    watch_stream::{
+        ConnectionStatus, ConsoleState, ConsoleStateWatch, Resource, ResourceId, TypeVisibility,
+        Viewer,
+    },
 };
 use axum::{
     async_trait,
@@ -24,52 +29,38 @@ use serde::{Deserialize, Serialize};
 
 pub struct ResourcesIndex {
     rx: ConsoleStateWatch,
+    _viewer: Viewer,
     paused_state: Option<ConsoleState>,
-    addr: ConsoleAddr,
-    connected: bool,
-    table_keybinds: TableViewKeybinds,
-    runtime_stats: HashMap<ResourceId, ResourceRuntimeStats>,
+    remote_pause_error: Option<String>,
+    source: StateSource,
+    table_keybinds: TableViewKeybinds<ResourceId>,
+    sort: Option<Sort<Column>>,
 }
 
 impl ResourcesIndex {
-    pub fn new(addr: ConsoleAddr, rx: ConsoleStateWatch) -> Self {
+    pub fn new(source: StateSource, rx: ConsoleStateWatch) -> Self {
         Self {
-            addr,
+            source,
+            _viewer: rx.viewer(),
             rx,
             paused_state: None,
-            connected: true,
+            remote_pause_error: None,
             table_keybinds: Default::default(),
-            runtime_stats: Default::default(),
+            sort: None,
         }
     }
 }
 
 impl ResourcesIndex {
-    fn state(&self) -> StateRef<'_, ConsoleState> {
-        let state = self.rx.borrow();
-        if let Some(state) = &self.paused_state {
-            StateRef::Ref(state)
-        } else {
-            StateRef::BorrowedFromWatch(state)
-        }
-    }
-
     fn navigate_to_resource_command(&self, id: ResourceId) -> JsCommand {
-        let uri = format!(
-            "/console/{}/{}/resources/{}",
-            self.addr.ip, self.addr.port, id.0
-        )
-        .parse()
-        .expect("invalid URI");
+        let uri = self
+            .source
+            .path(format!("resources/{}", id.0))
+            .parse()
+            .expect("invalid URI");
         js_command::navigate_to(uri)
     }
 
-    fn selected_resource(&self, idx: usize) -> Option<ResourceId> {
-        let state = self.state();
-        let resource = state.resources.values().nth(idx)?;
-        Some(resource.id)
-    }
-
     fn toggle_play_pause(&mut self) {
         if self.paused_state.is_some() {
             self.paused_state = None;
@@ -89,21 +80,43 @@ impl ResourcesIndex {
             Msg::TogglePlayPause => {
                 self.toggle_play_pause();
             }
+            Msg::ToggleRemotePause => {
+                let paused = self.rx.borrow().remote_paused;
+                self.remote_pause_error = self
+                    .rx
+                    .set_remote_paused(!paused)
+                    .await
+                    .err()
+                    .map(|err| format!("{:#}", err));
+            }
+            Msg::Sort(column) => {
+                self.sort_by(column);
+            }
             Msg::Key => match self.table_keybinds.update(data.as_ref()) {
-                Some(TableViewKeybindsUpdate::Selected(idx)) => {
-                    if let Some(id) = self.selected_resource(idx) {
-                        commands.push(self.navigate_to_resource_command(id));
-                    }
+                Some(TableViewKeybindsUpdate::Selected(id)) => {
+                    commands.push(self.navigate_to_resource_command(id));
+                }
+                Some(TableViewKeybindsUpdate::Move(delta)) => {
+                    self.move_selection(delta);
+                }
+                Some(TableViewKeybindsUpdate::Page(delta)) => {
+                    self.move_page(delta);
                 }
                 Some(TableViewKeybindsUpdate::TogglePlayPause) => {
                     self.toggle_play_pause();
                 }
                 Some(TableViewKeybindsUpdate::GotoResources) => {}
+                Some(TableViewKeybindsUpdate::Sort(change)) => {
+                    self.update_sort(change);
+                }
                 Some(TableViewKeybindsUpdate::GotoTasks) => {
                     commands.push(js_command::navigate_to(
-                        format!("/console/{}/{}/tasks", self.addr.ip, self.addr.port)
-                            .parse()
-                            .unwrap(),
+                        self.source.path("tasks").parse().unwrap(),
+                    ));
+                }
+                Some(TableViewKeybindsUpdate::GotoAsyncOps) => {
+                    commands.push(js_command::navigate_to(
+                        self.source.path("async-ops").parse().unwrap(),
                     ));
                 }
                 None => {}
@@ -111,34 +124,14 @@ impl ResourcesIndex {
             Msg::RowClick(id) => {
                 commands.push(self.navigate_to_resource_command(id));
             }
-            Msg::Update => {
-                if self.paused_state.is_none() {
-                    for resource in self.rx.borrow().resources.values() {
-                        let mut times = ResourceRuntimeStats::default();
-
-                        if let Some(total) = resource
-                            .stats
-                            .as_ref()
-                            .and_then(|s| s.created_at)
-                            .map(|t| t.elapsed().unwrap())
-                        {
-                            times.total = Some(total);
-                        }
-
-                        self.runtime_stats.insert(resource.id, times);
-                    }
-                }
-            }
-            Msg::Disconnected => {
-                self.connected = false;
-            }
+            // rows are read from the latest state when rendering
+            Msg::Update => {}
             Msg::Error => {
                 anyhow::bail!("console subscription disconnected")
             }
         }
 
-        let num_resources = self.state().resources.len();
-        self.table_keybinds.clamp_selected_idx(num_resources);
+        self.sync_selection();
 
         Ok(Updated::new(self).with_all(commands))
     }
@@ -165,7 +158,6 @@ impl LiveView for ResourcesIndex {
                     break;
                 }
             }
-            let _ = handle.send(Msg::Disconnected).await;
             let _ = handle.send(Msg::Error).await;
         });
         Ok(())
@@ -180,16 +172,18 @@ impl LiveView for ResourcesIndex {
     }
 
     fn render(&self) -> Html<Self::Message> {
+        let (connection, remote_paused, live) = {
+            let state = self.rx.borrow();
+            let live = state.connection == ConnectionStatus::Live;
+            (
+                render_connection(&self.source, &state),
+                state.remote_paused,
+                live,
+            )
+        };
+
         html! {
-            if self.connected {
-                <div>
-                    "Connection: " { &self.addr.ip } ":" { &self.addr.port }
-                </div>
-            } else {
-                <div>
-                    "Not connected..."
-                </div>
-            }
+            { connection }
 
             { self.table_keybinds.help() }
 
@@ -199,8 +193,18 @@ impl LiveView for ResourcesIndex {
                 } else {
                     <button axm-click={ Msg::TogglePlayPause }>"Pause"</button>
                 }
+
+                if remote_paused {
+                    <button axm-click={ Msg::ToggleRemotePause }>"Resume console"</button>
+                } else if live {
+                    <button axm-click={ Msg::ToggleRemotePause }>"Pause console for everyone"</button>
+                }
             </div>
 
+            if let Some(err) = &self.remote_pause_error {
+                <div>"Failed to pause or resume the console: " { err }</div>
+            }
+
             { self.table_render() }
         }
     }
@@ -209,73 +213,77 @@ impl LiveView for ResourcesIndex {
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum Msg {
     TogglePlayPause,
+    ToggleRemotePause,
     RowClick(ResourceId),
+    Sort(Column),
     Key,
     Update,
-    Disconnected,
     Error,
 }
 
-pub(crate) struct ResourceViewModel {
-    resource: Arc<Resource>,
-    runtime_stats: Option<ResourceRuntimeStats>,
-}
-
 impl TableView for ResourcesIndex {
     type Column = Column;
-    type Model = ResourceViewModel;
+    type Model = Arc<Resource>;
+    type Id = ResourceId;
     type Msg = Msg;
 
     fn columns(&self) -> Vec<Self::Column> {
         Column::all()
     }
 
+    fn state(&self) -> StateRef<'_, ConsoleState> {
+        let state = self.rx.borrow();
+        if let Some(state) = &self.paused_state {
+            StateRef::Ref(state)
+        } else {
+            StateRef::BorrowedFromWatch(state)
+        }
+    }
+
     fn rows(&self) -> Vec<Self::Model> {
-        self.state()
-            .resources
-            .values()
-            .map(|resource| ResourceViewModel {
-                resource: Arc::clone(resource),
-                runtime_stats: self.runtime_stats.get(&resource.id).copied(),
-            })
-            .collect()
+        self.state().resources.values().cloned().collect()
     }
 
-    fn render_column(&self, col: &Self::Column, row: &Self::Model) -> Html<Self::Msg> {
+    fn render_column(
+        &self,
+        col: &Self::Column,
+        row: &Self::Model,
+        state: &ConsoleState,
+    ) -> Html<Self::Msg> {
         match col {
             Column::ID => {
-                html! { { row.resource.id.0 } }
+                html! { { row.id.0 } }
             }
             Column::Parent => {
                 html! {
-                    if let Some(parent_id) = row.resource.parent_id {
+                    if let Some(parent_id) = row.parent_id {
                         { parent_id.0 }
                     }
                 }
             }
             Column::Kind => {
-                html! { { &row.resource.kind } }
+                html! { { &row.kind } }
             }
             Column::Total => {
                 html! {
-                    if let Some(total) = row.runtime_stats.and_then(|t| t.total) {
-                        { format!("{:?}", total) }
+                    if let Some(total) = row.filter_value("total", state) {
+                        { total.to_string() }
                     }
                 }
             }
             Column::Target => {
                 html! {
-                    if let Some(target) = &row.resource.target {
+                    if let Some(target) = &row.target {
                         <code>{ target }</code>
                     }
                 }
             }
             Column::Type => {
-                html! { { &row.resource.concrete_type } }
+                html! { { &row.concrete_type } }
             }
             Column::Vis => {
                 html! {
-                    match row.resource.vis {
+                    match row.vis {
                         TypeVisibility::Public => "✅",
                         TypeVisibility::Internal => "🔒",
                     }
@@ -283,7 +291,7 @@ impl TableView for ResourcesIndex {
             }
             Column::Location => {
                 html! {
-                    if let Some(location) = &row.resource.location {
+                    if let Some(location) = &row.location {
                         { location.render() }
                     } else {
                         "{unknown location}"
@@ -294,20 +302,53 @@ impl TableView for ResourcesIndex {
     }
 
     fn row_click_event(&self, row: &Self::Model) -> Self::Msg {
-        Msg::RowClick(row.resource.id)
+        Msg::RowClick(row.id)
     }
 
     fn key_event(&self) -> Self::Msg {
         Msg::Key
     }
 
-    fn row_selected(&self, idx: usize, _row: &Self::Model) -> bool {
-        self.table_keybinds.selected_idx() == Some(idx)
+    fn row_id(&self, row: &Self::Model) -> ResourceId {
+        row.id
+    }
+
+    fn sort(&self) -> Option<Sort<Column>> {
+        self.sort
+    }
+
+    fn set_sort(&mut self, sort: Option<Sort<Column>>) {
+        self.sort = sort;
+    }
+
+    fn sort_event(&self, col: &Column) -> Self::Msg {
+        Msg::Sort(*col)
+    }
+
+    fn keybinds(&self) -> &TableViewKeybinds<ResourceId> {
+        &self.table_keybinds
+    }
+
+    fn keybinds_mut(&mut self) -> &mut TableViewKeybinds<ResourceId> {
+        &mut self.table_keybinds
+    }
+
+    fn column_key(&self, col: &Column) -> &'static str {
+        match col {
+            Column::ID => "id",
+            Column::Parent => "parent",
+            Column::Kind => "kind",
+            Column::Total => "total",
+            Column::Target => "target",
+            Column::Type => "type",
+            Column::Vis => "vis",
+            Column::Location => "location",
+        }
     }
 }
 
 columns_enum! {
-    pub(crate) enum Column {
+    pub enum Column {
         ID,
         Parent,
         Kind,
@@ -318,8 +359,3 @@ columns_enum! {
         Location,
     }
 }
-
-#[derive(Default, Clone, Copy)]
-struct ResourceRuntimeStats {
-    total: Option<Duration>,
-}
@@ -1,27 +1,265 @@
+use super::{
+    filter::Filterable,
+    table_view_keybinds::{Selection, SortChange, TableViewKeybinds},
+    StateRef,
+};
+use crate::watch_stream::ConsoleState;
 use axum_live_view::{html, Html};
 
 pub(crate) trait TableView {
-    type Column: std::fmt::Display;
-    type Model;
+    type Column: std::fmt::Display + Copy + PartialEq;
+    /// Rows are filtered and sorted by their [`Filterable`] values.
+    type Model: Filterable;
+    type Id: Clone + PartialEq;
     type Msg;
 
     fn columns(&self) -> Vec<Self::Column>;
 
+    /// The state rows are taken from.
+    fn state(&self) -> StateRef<'_, ConsoleState>;
+
+    /// All rows, in no particular order.
     fn rows(&self) -> Vec<Self::Model>;
 
-    fn render_column(&self, col: &Self::Column, row: &Self::Model) -> Html<Self::Msg>;
+    /// Identifies the entity shown in a row, so it can be found again after the rows move around.
+    fn row_id(&self, row: &Self::Model) -> Self::Id;
+
+    fn render_column(
+        &self,
+        col: &Self::Column,
+        row: &Self::Model,
+        state: &ConsoleState,
+    ) -> Html<Self::Msg>;
+
+    /// The filter key a column is sorted by, such as `polls`.
+    fn column_key(&self, col: &Self::Column) -> &'static str;
+
+    fn sort(&self) -> Option<Sort<Self::Column>>;
+
+    fn set_sort(&mut self, sort: Option<Sort<Self::Column>>);
+
+    fn sort_event(&self, col: &Self::Column) -> Self::Msg;
 
     fn row_click_event(&self, row: &Self::Model) -> Self::Msg;
 
     fn key_event(&self) -> Self::Msg;
 
-    fn row_selected(&self, idx: usize, row: &Self::Model) -> bool;
+    fn keybinds(&self) -> &TableViewKeybinds<Self::Id>;
+
+    fn keybinds_mut(&mut self) -> &mut TableViewKeybinds<Self::Id>;
+
+    fn row_selected(&self, row: &Self::Model) -> bool {
+        self.keybinds()
+            .selected()
+            .map_or(false, |selection| selection.id == self.row_id(row))
+    }
+
+    /// The rows matching the current filter, in no particular order.
+    fn filtered_rows(&self) -> Vec<Self::Model> {
+        let filter = self.keybinds().filter();
+        let rows = self.rows();
+
+        if filter.is_empty() {
+            return rows;
+        }
+
+        let state = self.state();
+        rows.into_iter()
+            .filter(|row| filter.matches_entity(row, &state))
+            .collect()
+    }
+
+    /// The rows in the order they're shown.
+    fn sorted_rows(&self) -> Vec<Self::Model> {
+        let rows = self.filtered_rows();
+
+        let sort = if let Some(sort) = self.sort() {
+            sort
+        } else {
+            return rows;
+        };
 
+        let key = self.column_key(&sort.column);
+        let state = self.state();
+        let mut rows = rows
+            .into_iter()
+            .map(|row| (row.filter_value(key, &state), row))
+            .collect::<Vec<_>>();
+
+        // the sort is stable so rows with equal keys keep their order
+        rows.sort_by(|(a, _), (b, _)| if sort.descending { b.cmp(a) } else { a.cmp(b) });
+
+        rows.into_iter().map(|(_, row)| row).collect()
+    }
+
+    /// Move the selection `delta` rows, in the order rows are shown. Selects the first row if
+    /// nothing is selected.
+    fn move_selection(&mut self, delta: isize) {
+        let rows = self.sorted_rows();
+        if rows.is_empty() {
+            self.keybinds_mut().select(None);
+            return;
+        }
+
+        let last = rows.len() as isize - 1;
+        let idx = match self.selected_idx(&rows) {
+            Some(idx) => (idx as isize).saturating_add(delta).clamp(0, last) as usize,
+            // moving down from nothing selected lands on the first row
+            None => delta.saturating_sub(1).clamp(0, last) as usize,
+        };
+
+        let id = self.row_id(&rows[idx]);
+        self.keybinds_mut().select(Some(Selection { id, idx }));
+    }
+
+    /// Go `delta` pages down. The selection moves along if there is one, otherwise the first row
+    /// of the new page is selected.
+    fn move_page(&mut self, delta: isize) {
+        if self.keybinds().selected().is_some() {
+            self.move_selection(delta.saturating_mul(PAGE_SIZE as isize));
+            return;
+        }
+
+        let rows = self.sorted_rows();
+        if rows.is_empty() {
+            return;
+        }
+
+        let last_page = (rows.len() - 1) / PAGE_SIZE;
+        let page = (self.keybinds().page().min(last_page) as isize)
+            .saturating_add(delta)
+            .clamp(0, last_page as isize) as usize;
+
+        let idx = page * PAGE_SIZE;
+        let id = self.row_id(&rows[idx]);
+        self.keybinds_mut().select(Some(Selection { id, idx }));
+    }
+
+    /// Update the selection after rows have been added, removed or reordered.
+    ///
+    /// The same entity stays selected. If it has disappeared its nearest neighbour is selected
+    /// instead.
+    fn sync_selection(&mut self) {
+        if self.keybinds().selected().is_none() {
+            return;
+        }
+
+        let rows = self.sorted_rows();
+        let selection = self.selected_idx(&rows).map(|idx| Selection {
+            id: self.row_id(&rows[idx]),
+            idx,
+        });
+        self.keybinds_mut().select(selection);
+    }
+
+    /// Where the selected entity is in `rows`, or where its nearest neighbour is if it no longer
+    /// exists.
+    fn selected_idx(&self, rows: &[Self::Model]) -> Option<usize> {
+        let selection = self.keybinds().selected()?;
+
+        if rows.is_empty() {
+            return None;
+        }
+
+        let idx = rows
+            .iter()
+            .position(|row| self.row_id(row) == selection.id)
+            .unwrap_or_else(|| selection.idx.min(rows.len() - 1));
+
+        Some(idx)
+    }
+
+    /// Sort by `column`, or flip the direction if we're already sorting by it.
+    fn sort_by(&mut self, column: Self::Column) {
+        let sort = match self.sort() {
+            Some(sort) if sort.column == column => Sort {
+                column,
+                descending: !sort.descending,
+            },
+            _ => Sort {
+                column,
+                descending: false,
+            },
+        };
+
+        self.change_sort(sort);
+    }
+
+    fn update_sort(&mut self, change: SortChange) {
+        let columns = self.columns();
+
+        let current = if let Some(sort) = self.sort() {
+            sort
+        } else if let Some(&column) = columns.first() {
+            self.change_sort(Sort {
+                column,
+                descending: false,
+            });
+            return;
+        } else {
+            return;
+        };
+
+        let idx = columns
+            .iter()
+            .position(|col| *col == current.column)
+            .unwrap_or_default();
+
+        let sort = match change {
+            SortChange::PrevColumn => Sort {
+                column: columns[(idx + columns.len() - 1) % columns.len()],
+                ..current
+            },
+            SortChange::NextColumn => Sort {
+                column: columns[(idx + 1) % columns.len()],
+                ..current
+            },
+            SortChange::Invert => Sort {
+                descending: !current.descending,
+                ..current
+            },
+        };
+
+        self.change_sort(sort);
+    }
+
+    /// Change the sort while keeping the same entity selected.
+    fn change_sort(&mut self, sort: Sort<Self::Column>) {
+        self.set_sort(Some(sort));
+        self.sync_selection();
+    }
+
+    /// Render the current page of rows.
+    ///
+    /// Sorting and filtering apply to all rows but only one page is rendered, so huge tables
+    /// don't bog down the browser.
     fn table_render(&self) -> Html<Self::Msg> {
         let columns = self.columns();
-        let rows = self.rows();
+        let mut rows = self.sorted_rows();
+        let sort = self.sort();
+
+        let total = rows.len();
+        // rows might have disappeared since the page was chosen
+        let last_page = total.saturating_sub(1) / PAGE_SIZE;
+        let start = self.keybinds().page().min(last_page) * PAGE_SIZE;
+        let end = total.min(start + PAGE_SIZE);
+        rows.truncate(end);
+        rows.drain(..start);
+
+        let state = self.state();
 
         html! {
+            { self.keybinds().filter_box() }
+
+            <div class="table-page">
+                if total == 0 {
+                    "Showing 0 of 0"
+                } else {
+                    "Showing " { format_count(start + 1) } "–" { format_count(end) }
+                    " of " { format_count(total) }
+                }
+            </div>
+
             <table
                 class="resources-table"
                 axm-window-keydown={ self.key_event() }
@@ -29,18 +267,27 @@ pub(crate) trait TableView {
                 <thead>
                     <tr>
                         for col in &columns {
-                            <th>{ col.to_string() }</th>
+                            <th axm-click={ self.sort_event(col) }>
+                                { col.to_string() }
+                                if let Some(sort) = sort.filter(|sort| sort.column == *col) {
+                                    if sort.descending {
+                                        " ▼"
+                                    } else {
+                                        " ▲"
+                                    }
+                                }
+                            </th>
                         }
                     </tr>
                 </thead>
                 <tbody>
-                    for (idx, row) in rows.into_iter().enumerate() {
+                    for row in rows {
                         <tr
                             axm-click={ self.row_click_event(&row) }
-                            class=if self.row_selected(idx, &row) { "row-selected" }
+                            class=if self.row_selected(&row) { "row-selected" }
                         >
                             for col in &columns {
-                                <td>{ self.render_column(col, &row) }</td>
+                                <td>{ self.render_column(col, &row, &state) }</td>
                             }
                         </tr>
                     }
@@ -49,3 +296,25 @@ pub(crate) trait TableView {
         }
     }
 }
+
+/// The number of rows rendered at a time.
+pub(crate) const PAGE_SIZE: usize = 200;
+
+/// Format a count with thousands separators, like `48,312`.
+fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (idx, digit) in digits.chars().enumerate() {
+        if idx != 0 && (digits.len() - idx) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Sort<C> {
+    pub(crate) column: C,
+    pub(crate) descending: bool,
+}
@@ -1,46 +1,81 @@
+use super::{filter::Filter, table::PAGE_SIZE};
 use axum_live_view::{event_data::EventData, html, Html};
 
-#[derive(Default)]
-pub(crate) struct TableViewKeybinds {
-    selected_idx: Option<usize>,
+pub(crate) struct TableViewKeybinds<Id> {
+    selected: Option<Selection<Id>>,
+    /// The page being shown, which is the one containing the selection if there is one.
+    page: usize,
     show_key_binds: bool,
+    filter: Filter,
+    editing_filter: bool,
 }
 
-impl TableViewKeybinds {
-    pub(crate) fn selected_idx(&self) -> Option<usize> {
-        self.selected_idx
+/// The selected entity and where it was last seen, so a neighbour can be selected if it
+/// disappears.
+#[derive(Clone)]
+pub(crate) struct Selection<Id> {
+    pub(crate) id: Id,
+    pub(crate) idx: usize,
+}
+
+impl<Id> Default for TableViewKeybinds<Id> {
+    fn default() -> Self {
+        Self {
+            selected: None,
+            page: 0,
+            show_key_binds: false,
+            filter: Default::default(),
+            editing_filter: false,
+        }
+    }
+}
+
+impl<Id> TableViewKeybinds<Id>
+where
+    Id: Clone,
+{
+    pub(crate) fn selected(&self) -> Option<&Selection<Id>> {
+        self.selected.as_ref()
     }
 
-    pub(crate) fn clamp_selected_idx(&mut self, new_max: usize) {
-        if let Some(idx) = self.selected_idx.as_mut() {
-            *idx = std::cmp::min(new_max - 1, *idx);
+    pub(crate) fn select(&mut self, selection: Option<Selection<Id>>) {
+        if let Some(selection) = &selection {
+            self.page = selection.idx / PAGE_SIZE;
         }
+        self.selected = selection;
+    }
+
+    pub(crate) fn page(&self) -> usize {
+        self.page
+    }
+
+    pub(crate) fn filter(&self) -> &Filter {
+        &self.filter
     }
 
-    pub(crate) fn update(&mut self, data: Option<&EventData>) -> Option<TableViewKeybindsUpdate> {
+    pub(crate) fn update(
+        &mut self,
+        data: Option<&EventData>,
+    ) -> Option<TableViewKeybindsUpdate<Id>> {
         let data = data.unwrap();
         let key = data.as_key().unwrap().key();
 
-        match key {
-            "k" => {
-                if let Some(idx) = self.selected_idx.as_mut() {
-                    if *idx != 0 {
-                        *idx -= 1;
-                    }
-                }
-
-                None
-            }
-            "j" => {
-                if let Some(idx) = self.selected_idx.as_mut() {
-                    *idx += 1;
-                } else {
-                    self.selected_idx = Some(0);
-                }
+        if self.editing_filter {
+            self.edit_filter(key);
+            return None;
+        }
 
-                None
-            }
-            "Enter" => self.selected_idx.map(TableViewKeybindsUpdate::Selected),
+        match key {
+            "k" => Some(TableViewKeybindsUpdate::Move(-1)),
+            "j" => Some(TableViewKeybindsUpdate::Move(1)),
+            "PageUp" => Some(TableViewKeybindsUpdate::Page(-1)),
+            "PageDown" => Some(TableViewKeybindsUpdate::Page(1)),
+            "g" => Some(TableViewKeybindsUpdate::Move(isize::MIN)),
+            "G" => Some(TableViewKeybindsUpdate::Move(isize::MAX)),
+            "Enter" => self
+                .selected
+                .as_ref()
+                .map(|selection| TableViewKeybindsUpdate::Selected(selection.id.clone())),
             " " => Some(TableViewKeybindsUpdate::TogglePlayPause),
             "?" => {
                 self.show_key_binds = !self.show_key_binds;
@@ -48,20 +83,77 @@ impl TableViewKeybinds {
             }
             "t" => Some(TableViewKeybindsUpdate::GotoTasks),
             "r" => Some(TableViewKeybindsUpdate::GotoResources),
+            "a" => Some(TableViewKeybindsUpdate::GotoAsyncOps),
+            "<" => Some(TableViewKeybindsUpdate::Sort(SortChange::PrevColumn)),
+            ">" => Some(TableViewKeybindsUpdate::Sort(SortChange::NextColumn)),
+            "i" => Some(TableViewKeybindsUpdate::Sort(SortChange::Invert)),
+            "/" => {
+                self.editing_filter = true;
+                None
+            }
             _ => None,
         }
     }
 
+    fn edit_filter(&mut self, key: &str) {
+        let mut query = self.filter.query().to_owned();
+
+        match key {
+            "Enter" => {
+                self.editing_filter = false;
+                return;
+            }
+            "Escape" => {
+                self.editing_filter = false;
+                query.clear();
+            }
+            "Backspace" => {
+                query.pop();
+            }
+            // ignore modifiers, arrow keys and such
+            key if key.chars().count() == 1 => {
+                query.push_str(key);
+            }
+            _ => return,
+        }
+
+        self.filter = Filter::parse(&query);
+    }
+
+    pub(crate) fn filter_box<T>(&self) -> Html<T> {
+        if self.editing_filter || !self.filter.is_empty() {
+            html! {
+                <div class="filter">
+                    "Filter: "
+                    <code>
+                        { self.filter.query() }
+                        if self.editing_filter {
+                            "▏"
+                        }
+                    </code>
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
     pub(crate) fn help<T>(&self) -> Html<T> {
         if self.show_key_binds {
             html! {
                 <div class="keybinds">
                     "Key binds<br>"
                     "j/k: down/up<br>"
+                    "PageDown/PageUp: next/previous page<br>"
+                    "g/G: first/last row<br>"
                     "space: play/pause<br>"
                     "enter: open<br>"
-                    "t: goto tasks"
-                    "r: goto resources"
+                    "t: goto tasks<br>"
+                    "r: goto resources<br>"
+                    "a: goto async ops<br>"
+                    "&lt;/&gt;: sort by previous/next column<br>"
+                    "i: invert sort<br>"
+                    "/: filter, enter to close, escape to clear<br>"
                     "?: show/hide keybinds"
                 </div>
             }
@@ -71,9 +163,21 @@ impl TableViewKeybinds {
     }
 }
 
-pub(crate) enum TableViewKeybindsUpdate {
+pub(crate) enum TableViewKeybindsUpdate<Id> {
     TogglePlayPause,
-    Selected(usize),
+    Selected(Id),
+    /// Move the selection this many rows down, in the order rows are shown.
+    Move(isize),
+    /// Go this many pages down.
+    Page(isize),
     GotoTasks,
     GotoResources,
+    GotoAsyncOps,
+    Sort(SortChange),
+}
+
+pub(crate) enum SortChange {
+    PrevColumn,
+    NextColumn,
+    Invert,
 }
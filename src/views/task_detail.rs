@@ -0,0 +1,304 @@
+use super::{render_connection, render_time};
+use crate::{
+    routes::StateSource,
+    watch_stream::{ConsoleStateWatch, Task, TaskDetails, TaskId, TaskState, Viewer},
+};
+use axum::{
+    async_trait,
+    http::{HeaderMap, Uri},
+};
+use axum_live_view::{
+    event_data::EventData,
+    html,
+    live_view::{Updated, ViewHandle},
+    Html, LiveView,
+};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use tokio_stream::StreamExt;
+
+pub struct TaskDetail {
+    rx: ConsoleStateWatch,
+    _viewer: Viewer,
+    source: StateSource,
+    id: TaskId,
+    details: Option<TaskDetails>,
+}
+
+impl TaskDetail {
+    pub fn new(source: StateSource, rx: ConsoleStateWatch, id: TaskId) -> Self {
+        Self {
+            _viewer: rx.viewer(),
+            rx,
+            source,
+            id,
+            details: None,
+        }
+    }
+}
+
+#[async_trait]
+impl LiveView for TaskDetail {
+    type Message = Msg;
+    type Error = anyhow::Error;
+
+    async fn mount(
+        &mut self,
+        _uri: Uri,
+        _request_headers: &HeaderMap,
+        handle: ViewHandle<Self::Message>,
+    ) -> Result<(), Self::Error> {
+        match self.rx.watch_task_details(self.id).await {
+            Ok(mut details) => {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    while let Some(details) = details.next().await {
+                        let details = match details {
+                            Ok(details) => details,
+                            Err(err) => {
+                                tracing::debug!(%err, "task details stream ended");
+                                break;
+                            }
+                        };
+                        if handle.send(Msg::Details(details)).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            Err(err) => {
+                tracing::debug!(%err, id = ?self.id, "failed to watch task details");
+            }
+        }
+
+        let mut rx = self.rx.clone();
+        tokio::spawn(async move {
+            loop {
+                if rx.changed().await.is_err() {
+                    break;
+                }
+                if handle.send(Msg::Update).await.is_err() {
+                    break;
+                }
+            }
+            let _ = handle.send(Msg::Error).await;
+        });
+
+        Ok(())
+    }
+
+    async fn update(
+        mut self,
+        msg: Self::Message,
+        _data: Option<EventData>,
+    ) -> Result<Updated<Self>, Self::Error> {
+        match msg {
+            Msg::Update => {}
+            Msg::Details(details) => {
+                self.details = Some(details);
+            }
+            Msg::Error => {
+                anyhow::bail!("console subscription disconnected")
+            }
+        }
+
+        Ok(Updated::new(self))
+    }
+
+    fn render(&self) -> Html<Self::Message> {
+        let (task, warnings, connection, now) = {
+            let state = self.rx.borrow();
+            let task = state.tasks.get(&self.id).cloned();
+            let warnings = state.warnings.get(&self.id).cloned().unwrap_or_default();
+            let connection = render_connection(&self.source, &state);
+            (task, warnings, connection, state.now)
+        };
+
+        html! {
+            { connection }
+
+            if let Some(task) = task {
+                for warning in &warnings {
+                    <div>"⚠ " { warning.to_string() }</div>
+                }
+
+                { self.render_task(&task, now) }
+            } else {
+                <p>
+                    "Task " { self.id.0 } " no longer exists."
+                </p>
+                <a href={ self.source.path("tasks") }>
+                    "Back to tasks"
+                </a>
+            }
+        }
+    }
+}
+
+impl TaskDetail {
+    fn render_task(&self, task: &Task, now: Option<SystemTime>) -> Html<Msg> {
+        let state = match task.state() {
+            TaskState::Running => "▶️ running",
+            TaskState::Idle => "⏸ idle",
+            TaskState::Completed => "⏹ completed",
+        };
+
+        html! {
+            <h2>
+                "Task " { task.id.0 }
+                if let Some(name) = task.name() {
+                    " " <code>{ name }</code>
+                }
+            </h2>
+
+            <table class="details-table">
+                <tr>
+                    <th>"State"</th>
+                    <td>{ state }</td>
+                </tr>
+                <tr>
+                    <th>"Target"</th>
+                    <td>
+                        if let Some(target) = &task.target {
+                            <code>{ target }</code>
+                        }
+                    </td>
+                </tr>
+                <tr>
+                    <th>"Location"</th>
+                    <td><code>{ task.location.render() }</code></td>
+                </tr>
+                <tr>
+                    <th>"Fields"</th>
+                    <td>
+                        for (name, value) in task.fields.iter().filter(|(name, _)| name != &"task.name") {
+                            <div>
+                                <code>{ format!("{}={}", name, value) }</code>
+                            </div>
+                        }
+                    </td>
+                </tr>
+                if let Some(stats) = &task.stats {
+                    <tr>
+                        <th>"Created at"</th>
+                        <td>
+                            if let Some(created_at) = stats.created_at {
+                                { render_time(created_at) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Dropped at"</th>
+                        <td>
+                            if let Some(dropped_at) = stats.dropped_at {
+                                { render_time(dropped_at) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Busy"</th>
+                        <td>
+                            if let Some(busy) = stats.busy_time {
+                                { format!("{:?}", busy) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Idle"</th>
+                        <td>
+                            if let Some(idle) = now.and_then(|now| stats.idle_time(now)) {
+                                { format!("{:?}", idle) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Polls"</th>
+                        <td>{ stats.polls }</td>
+                    </tr>
+                    <tr>
+                        <th>"Last poll started"</th>
+                        <td>
+                            if let Some(started) = stats.last_poll_started {
+                                { format!("{:?}", started) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Last poll ended"</th>
+                        <td>
+                            if let Some(ended) = stats.last_poll_ended {
+                                { format!("{:?}", ended) }
+                            }
+                        </td>
+                    </tr>
+                    <tr>
+                        <th>"Wakes"</th>
+                        <td>{ stats.wakes }</td>
+                    </tr>
+                    <tr>
+                        <th>"Self-wakes"</th>
+                        <td>{ stats.self_wakes }</td>
+                    </tr>
+                    <tr>
+                        <th>"Waker clones"</th>
+                        <td>{ stats.waker_clones }</td>
+                    </tr>
+                    <tr>
+                        <th>"Waker drops"</th>
+                        <td>{ stats.waker_drops }</td>
+                    </tr>
+                    <tr>
+                        <th>"Waker count"</th>
+                        <td>{ stats.waker_count() }</td>
+                    </tr>
+                    <tr>
+                        <th>"Last wake"</th>
+                        <td>
+                            if let Some(last_wake) = stats.last_wake {
+                                { render_time(last_wake) }
+                            }
+                        </td>
+                    </tr>
+                }
+            </table>
+
+            <h3>"Poll times"</h3>
+
+            if let Some(poll_times) = self.details.as_ref().and_then(|d| d.poll_times.as_ref()) {
+                <table class="details-table">
+                    <tr>
+                        <th>"Samples"</th>
+                        <td>{ poll_times.count }</td>
+                    </tr>
+                    <tr>
+                        <th>"Min"</th>
+                        <td>{ format!("{:?}", poll_times.min) }</td>
+                    </tr>
+                    <tr>
+                        <th>"Mean"</th>
+                        <td>{ format!("{:?}", poll_times.mean) }</td>
+                    </tr>
+                    for (percentile, value) in &poll_times.percentiles {
+                        <tr>
+                            <th>{ format!("p{}", percentile) }</th>
+                            <td>{ format!("{:?}", value) }</td>
+                        </tr>
+                    }
+                    <tr>
+                        <th>"Max"</th>
+                        <td>{ format!("{:?}", poll_times.max) }</td>
+                    </tr>
+                </table>
+            } else {
+                <p>"No poll times recorded yet"</p>
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum Msg {
+    Update,
+    Details(TaskDetails),
+    Error,
+}
@@ -1,11 +1,15 @@
 use super::{
-    table::TableView,
+    filter::Filterable,
+    render_connection,
+    table::{Sort, TableView},
     table_view_keybinds::{TableViewKeybinds, TableViewKeybindsUpdate},
     StateRef,
 };
 use crate::{
-    routes::ConsoleAddr,
-    watch_stream::{ConsoleState, ConsoleStateWatch, Task, TaskId, TaskState},
+    routes::StateSource,
+    watch_stream::{
+        ConnectionStatus, ConsoleState, ConsoleStateWatch, Task, TaskId, TaskState, Viewer,
+    },
 };
 use axum::{
     async_trait,
@@ -19,26 +23,30 @@ use axum_live_view::{
     Html, LiveView,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, sync::Arc};
 
 pub struct TasksIndex {
     rx: ConsoleStateWatch,
+    _viewer: Viewer,
     paused_state: Option<ConsoleState>,
-    addr: ConsoleAddr,
-    connected: bool,
-    runtime_stats: HashMap<TaskId, TaskRuntimeStats>,
+    remote_pause_error: Option<String>,
+    source: StateSource,
+    hide_completed: bool,
+    sort: Option<Sort<Column>>,
     tally: Tally,
-    table_keybinds: TableViewKeybinds,
+    table_keybinds: TableViewKeybinds<TaskId>,
 }
 
 impl TasksIndex {
-    pub fn new(addr: ConsoleAddr, rx: ConsoleStateWatch) -> Self {
+    pub fn new(source: StateSource, rx: ConsoleStateWatch) -> Self {
         Self {
-            addr,
+            source,
+            _viewer: rx.viewer(),
             rx,
             paused_state: None,
-            connected: true,
-            runtime_stats: Default::default(),
+            remote_pause_error: None,
+            hide_completed: false,
+            sort: None,
             tally: Default::default(),
             table_keybinds: Default::default(),
         }
@@ -46,13 +54,18 @@ impl TasksIndex {
 }
 
 impl TasksIndex {
-    fn state(&self) -> StateRef<'_, ConsoleState> {
-        let state = self.rx.borrow();
-        if let Some(state) = &self.paused_state {
-            StateRef::Ref(state)
-        } else {
-            StateRef::BorrowedFromWatch(state)
-        }
+    pub fn hide_completed(mut self, hide_completed: bool) -> Self {
+        self.hide_completed = hide_completed;
+        self
+    }
+
+    /// The tasks shown in the table.
+    fn visible_tasks<'a>(&self, state: &'a ConsoleState) -> impl Iterator<Item = &'a Arc<Task>> {
+        let hide_completed = self.hide_completed;
+        state
+            .tasks
+            .values()
+            .filter(move |task| !(hide_completed && task.is_completed()))
     }
 }
 
@@ -62,6 +75,8 @@ struct Tally {
     running: usize,
     idle: usize,
     completed: usize,
+    with_warnings: usize,
+    warnings_by_lint: BTreeMap<String, usize>,
 }
 
 #[async_trait]
@@ -85,7 +100,6 @@ impl LiveView for TasksIndex {
                     break;
                 }
             }
-            let _ = handle.send(Msg::Disconnected).await;
             let _ = handle.send(Msg::Error).await;
         });
         Ok(())
@@ -100,16 +114,23 @@ impl LiveView for TasksIndex {
     }
 
     fn render(&self) -> Html<Self::Message> {
+        let (connection, remote_paused, live) = {
+            let state = self.rx.borrow();
+            let live = state.connection == ConnectionStatus::Live;
+            (
+                render_connection(&self.source, &state),
+                state.remote_paused,
+                live,
+            )
+        };
+        let matching = if self.table_keybinds.filter().is_empty() {
+            None
+        } else {
+            Some(self.filtered_rows().len())
+        };
+
         html! {
-            if self.connected {
-                <div>
-                    "Connection: " { &self.addr.ip } ":" { &self.addr.port }
-                </div>
-            } else {
-                <div>
-                    "Not connected..."
-                </div>
-            }
+            { connection }
 
             { self.table_keybinds.help() }
 
@@ -127,16 +148,49 @@ impl LiveView for TasksIndex {
                 if self.tally.completed != 0 {
                     ", completed: " { self.tally.completed }
                 }
+
+                if let Some(matching) = matching {
+                    ", matching filter: " { matching }
+                }
             </div>
 
+            if self.tally.with_warnings != 0 {
+                <div>
+                    "⚠ Warnings: " { self.tally.with_warnings } " tasks ("
+                    for (idx, (lint, count)) in self.tally.warnings_by_lint.iter().enumerate() {
+                        if idx != 0 {
+                            ", "
+                        }
+                        { lint } ": " { count }
+                    }
+                    ")"
+                </div>
+            }
+
             <div>
                 if self.paused_state.is_some() {
                     <button axm-click={ Msg::TogglePlayPause }>"Play"</button>
                 } else {
                     <button axm-click={ Msg::TogglePlayPause }>"Pause"</button>
                 }
+
+                if remote_paused {
+                    <button axm-click={ Msg::ToggleRemotePause }>"Resume console"</button>
+                } else if live {
+                    <button axm-click={ Msg::ToggleRemotePause }>"Pause console for everyone"</button>
+                }
+
+                if self.hide_completed {
+                    <button axm-click={ Msg::ToggleHideCompleted }>"Show completed"</button>
+                } else {
+                    <button axm-click={ Msg::ToggleHideCompleted }>"Hide completed"</button>
+                }
             </div>
 
+            if let Some(err) = &self.remote_pause_error {
+                <div>"Failed to pause or resume the console: " { err }</div>
+            }
+
             { self.table_render() }
         }
     }
@@ -145,9 +199,11 @@ impl LiveView for TasksIndex {
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum Msg {
     TogglePlayPause,
+    ToggleRemotePause,
+    ToggleHideCompleted,
     RowClick(TaskId),
+    Sort(Column),
     Update,
-    Disconnected,
     Error,
     Key,
 }
@@ -164,54 +220,60 @@ impl TasksIndex {
             Msg::TogglePlayPause => {
                 self.toggle_play_pause();
             }
+            Msg::ToggleRemotePause => {
+                let paused = self.rx.borrow().remote_paused;
+                self.remote_pause_error = self
+                    .rx
+                    .set_remote_paused(!paused)
+                    .await
+                    .err()
+                    .map(|err| format!("{:#}", err));
+            }
+            Msg::ToggleHideCompleted => {
+                self.hide_completed = !self.hide_completed;
+            }
             Msg::RowClick(task_id) => {
                 commands.push(self.navigate_to_task_command(task_id));
             }
+            Msg::Sort(column) => {
+                self.sort_by(column);
+            }
             Msg::Key => match self.table_keybinds.update(data.as_ref()) {
-                Some(TableViewKeybindsUpdate::Selected(idx)) => {
-                    if let Some(id) = self.selected_task(idx) {
-                        commands.push(self.navigate_to_task_command(id));
-                    }
+                Some(TableViewKeybindsUpdate::Selected(id)) => {
+                    commands.push(self.navigate_to_task_command(id));
+                }
+                Some(TableViewKeybindsUpdate::Move(delta)) => {
+                    self.move_selection(delta);
+                }
+                Some(TableViewKeybindsUpdate::Page(delta)) => {
+                    self.move_page(delta);
                 }
                 Some(TableViewKeybindsUpdate::GotoTasks) => {}
+                Some(TableViewKeybindsUpdate::Sort(change)) => {
+                    self.update_sort(change);
+                }
                 Some(TableViewKeybindsUpdate::GotoResources) => {
                     commands.push(js_command::navigate_to(
-                        format!("/console/{}/{}/resources", self.addr.ip, self.addr.port)
-                            .parse()
-                            .unwrap(),
+                        self.source.path("resources").parse().unwrap(),
                     ));
                 }
                 Some(TableViewKeybindsUpdate::TogglePlayPause) => {
                     self.toggle_play_pause();
                 }
+                Some(TableViewKeybindsUpdate::GotoAsyncOps) => {
+                    commands.push(js_command::navigate_to(
+                        self.source.path("async-ops").parse().unwrap(),
+                    ));
+                }
                 None => {}
             },
             Msg::Update => {
                 if self.paused_state.is_none() {
                     self.tally = Default::default();
 
-                    for task in self.rx.borrow().tasks.values() {
-                        let mut times = TaskRuntimeStats::default();
-
-                        if let Some(total) = task
-                            .stats
-                            .as_ref()
-                            .and_then(|s| s.created_at)
-                            .map(|t| t.elapsed().unwrap())
-                        {
-                            times.total = Some(total);
-                        }
-
-                        if let Some(busy) = task.stats.as_ref().and_then(|s| s.busy_time) {
-                            times.busy = Some(busy);
-                        }
-
-                        if let Some(idle) = task.stats.as_ref().and_then(|s| s.idle_time()) {
-                            times.idle = Some(idle);
-                        }
-
-                        self.runtime_stats.insert(task.id, times);
+                    let state = self.rx.borrow();
 
+                    for task in state.tasks.values() {
                         self.tally.total += 1;
                         match task.state() {
                             TaskState::Running => self.tally.running += 1,
@@ -219,35 +281,35 @@ impl TasksIndex {
                             TaskState::Completed => self.tally.completed += 1,
                         }
                     }
+
+                    for warnings in state.warnings.values() {
+                        self.tally.with_warnings += 1;
+                        for warning in warnings {
+                            *self
+                                .tally
+                                .warnings_by_lint
+                                .entry(warning.lint.clone())
+                                .or_default() += 1;
+                        }
+                    }
                 }
             }
-            Msg::Disconnected => {
-                self.connected = false;
-            }
             Msg::Error => {
                 anyhow::bail!("console subscription disconnected")
             }
         };
 
-        let num_tasks = self.state().tasks.len();
-        self.table_keybinds.clamp_selected_idx(num_tasks);
+        self.sync_selection();
 
         Ok(Updated::new(self).with_all(commands))
     }
 
-    fn selected_task(&self, idx: usize) -> Option<TaskId> {
-        let state = self.state();
-        let task = state.tasks.values().nth(idx)?;
-        Some(task.id)
-    }
-
     fn navigate_to_task_command(&self, id: TaskId) -> JsCommand {
-        let uri = format!(
-            "/console/{}/{}/tasks/{}",
-            self.addr.ip, self.addr.port, id.0
-        )
-        .parse()
-        .expect("invalid URI");
+        let uri = self
+            .source
+            .path(format!("tasks/{}", id.0))
+            .parse()
+            .expect("invalid URI");
         js_command::navigate_to(uri)
     }
 
@@ -260,68 +322,112 @@ impl TasksIndex {
     }
 }
 
-pub(crate) struct TaskViewModel {
-    task: Arc<Task>,
-    runtime_stats: Option<TaskRuntimeStats>,
-}
-
-#[derive(Default, Clone, Copy)]
-struct TaskRuntimeStats {
-    total: Option<Duration>,
-    busy: Option<Duration>,
-    idle: Option<Duration>,
-}
-
 impl TableView for TasksIndex {
     type Column = Column;
-    type Model = TaskViewModel;
+    type Model = Arc<Task>;
+    type Id = TaskId;
     type Msg = Msg;
 
     fn columns(&self) -> Vec<Self::Column> {
         Column::all()
     }
 
+    fn state(&self) -> StateRef<'_, ConsoleState> {
+        let state = self.rx.borrow();
+        if let Some(state) = &self.paused_state {
+            StateRef::Ref(state)
+        } else {
+            StateRef::BorrowedFromWatch(state)
+        }
+    }
+
     fn rows(&self) -> Vec<Self::Model> {
-        self.state()
-            .tasks
-            .values()
-            .map(|task| TaskViewModel {
-                task: Arc::clone(task),
-                runtime_stats: self.runtime_stats.get(&task.id).copied(),
-            })
-            .collect()
+        let state = self.state();
+        self.visible_tasks(&state).cloned().collect()
+    }
+
+    fn row_id(&self, row: &Arc<Task>) -> TaskId {
+        row.id
     }
 
-    fn row_click_event(&self, row: &TaskViewModel) -> Self::Msg {
-        Msg::RowClick(row.task.id)
+    fn row_click_event(&self, row: &Arc<Task>) -> Self::Msg {
+        Msg::RowClick(row.id)
     }
 
     fn key_event(&self) -> Self::Msg {
         Msg::Key
     }
 
-    fn row_selected(&self, idx: usize, _: &Self::Model) -> bool {
-        self.table_keybinds.selected_idx() == Some(idx)
+    fn sort(&self) -> Option<Sort<Column>> {
+        self.sort
+    }
+
+    fn set_sort(&mut self, sort: Option<Sort<Column>>) {
+        self.sort = sort;
     }
 
-    fn render_column(&self, col: &Self::Column, row: &TaskViewModel) -> Html<Self::Msg> {
+    fn sort_event(&self, col: &Column) -> Self::Msg {
+        Msg::Sort(*col)
+    }
+
+    fn keybinds(&self) -> &TableViewKeybinds<TaskId> {
+        &self.table_keybinds
+    }
+
+    fn keybinds_mut(&mut self) -> &mut TableViewKeybinds<TaskId> {
+        &mut self.table_keybinds
+    }
+
+    fn column_key(&self, col: &Column) -> &'static str {
+        match col {
+            Column::ID => "id",
+            Column::Warnings => "warnings",
+            Column::State => "state",
+            Column::Name => "name",
+            Column::Total => "total",
+            Column::Busy => "busy",
+            Column::Idle => "idle",
+            Column::Polls => "polls",
+            Column::Wakes => "wakes",
+            Column::WakerCount => "wakers",
+            Column::SelfWakes => "self_wakes",
+            Column::LastWake => "last_wake",
+            Column::Target => "target",
+            Column::Location => "location",
+            Column::Fields => "fields",
+        }
+    }
+
+    fn render_column(
+        &self,
+        col: &Self::Column,
+        row: &Arc<Task>,
+        state: &ConsoleState,
+    ) -> Html<Self::Msg> {
         match col {
             Column::ID => {
-                html! { { row.task.id.0 } }
+                html! { { row.id.0 } }
+            }
+            Column::Warnings => {
+                html! {
+                    for warning in state.warnings.get(&row.id).into_iter().flatten() {
+                        <div title={ warning.to_string() }>"⚠ " { &warning.lint }</div>
+                    }
+                }
             }
             Column::State => {
-                let state = match row.task.state() {
+                let icon = match row.state() {
                     TaskState::Running => "▶️",
                     TaskState::Idle => "⏸",
                     TaskState::Completed => "⏹",
                 };
 
-                html! { { state } }
+                html! { { icon } }
             }
             Column::Name => {
                 html! {
                     <code>
-                        if let Some(name) = row.task.name() {
+                        if let Some(name) = row.name() {
                             { name }
                         } else {
                             ""
@@ -329,37 +435,51 @@ impl TableView for TasksIndex {
                     </code>
                 }
             }
-            Column::Total => {
+            Column::Total | Column::Busy | Column::Idle => {
                 html! {
-                    if let Some(total) = row.runtime_stats.and_then(|t| t.total) {
-                        { format!("{:?}", total) }
+                    if let Some(duration) = row.filter_value(self.column_key(col), state) {
+                        { duration.to_string() }
                     }
                 }
             }
-            Column::Busy => {
+            Column::Polls => {
                 html! {
-                    if let Some(busy) = row.runtime_stats.and_then(|t| t.busy) {
-                        { format!("{:?}", busy) }
+                    if let Some(stats) = &row.stats {
+                        { stats.polls }
                     }
                 }
             }
-            Column::Idle => {
+            Column::Wakes => {
                 html! {
-                    if let Some(idle) = row.runtime_stats.and_then(|t| t.idle) {
-                        { format!("{:?}", idle) }
+                    if let Some(stats) = &row.stats {
+                        { stats.wakes }
                     }
                 }
             }
-            Column::Polls => {
+            Column::WakerCount => {
                 html! {
-                    if let Some(stats) = &row.task.stats {
-                        { stats.polls }
+                    if let Some(stats) = &row.stats {
+                        { stats.waker_count() }
+                    }
+                }
+            }
+            Column::SelfWakes => {
+                html! {
+                    if let Some(stats) = &row.stats {
+                        { stats.self_wakes }
+                    }
+                }
+            }
+            Column::LastWake => {
+                html! {
+                    if let Some(since_last_wake) = row.filter_value("last_wake", state) {
+                        { format!("{} ago", since_last_wake) }
                     }
                 }
             }
             Column::Target => {
                 html! {
-                    if let Some(target) = &row.task.target {
+                    if let Some(target) = &row.target {
                         <code>{ target }</code>
                     }
                 }
@@ -367,13 +487,13 @@ impl TableView for TasksIndex {
             Column::Location => {
                 html! {
                     <code>
-                        { row.task.location.render() }
+                        { row.location.render() }
                     </code>
                 }
             }
             Column::Fields => {
                 html! {
-                    for (name, value) in row.task.fields.iter().filter(|(name, _)| name != &"task.name") {
+                    for (name, value) in row.fields.iter().filter(|(name, _)| name != &"task.name") {
                         <code>
                             { format!("{}={}", name, value) }
                         </code>
@@ -385,14 +505,19 @@ impl TableView for TasksIndex {
 }
 
 columns_enum! {
-    pub(crate) enum Column {
+    pub enum Column {
         ID,
+        Warnings,
         State,
         Name,
         Total,
         Busy,
         Idle,
         Polls,
+        Wakes,
+        WakerCount = "Waker count",
+        SelfWakes = "Self-wakes",
+        LastWake = "Last wake",
         Target,
         Location,
         Fields,
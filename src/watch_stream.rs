@@ -1,78 +1,580 @@
-use crate::{routes::ConsoleAddr, InstrumentClient};
+use crate::{
+    allowlist::Allowlist,
+    console_addr::ConsoleAddr,
+    lints::{Linter, Warning},
+    recording::Recorder,
+    tls::TlsOptions,
+    InstrumentClient,
+};
 use anyhow::Context as _;
-use console_api::instrument::InstrumentRequest;
+use console_api::instrument::{InstrumentRequest, PauseRequest, ResumeRequest, TaskDetailsRequest};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{hash_map::Entry, BTreeMap, HashMap},
     fmt,
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     time::{Duration, SystemTime},
 };
 use tokio::{
-    sync::{watch, Mutex},
+    sync::{broadcast, mpsc, oneshot, watch, Mutex, Notify},
     time::Instant,
 };
-use tokio_stream::{wrappers::IntervalStream, StreamExt};
-use tonic::{transport::Endpoint, Streaming};
+use tokio_stream::{wrappers::IntervalStream, Stream, StreamExt};
+use tonic::Streaming;
+
+type SubscriptionMap = Arc<Mutex<HashMap<ConsoleAddr, Subscription>>>;
+
+/// Where raw updates are sent. Shared with every [`ConsoleStateWatch`] so they can subscribe, and
+/// taken by the pipeline when it ends so receivers don't have to wait for every watch to go away.
+type RawUpdates = Arc<parking_lot::Mutex<Option<broadcast::Sender<Arc<RawUpdate>>>>>;
 
 #[derive(Clone, Default)]
 pub struct ConsoleSubscriptions {
-    inner: Arc<Mutex<HashMap<ConsoleAddr, ConsoleStateWatch>>>,
+    inner: SubscriptionMap,
+    linter: Arc<Linter>,
+    retention: Retention,
+    record_all: Option<Recorder>,
+    allowlist: Allowlist,
+    tls: Arc<parking_lot::Mutex<HashMap<ConsoleAddr, TlsOptions>>>,
 }
 
 impl ConsoleSubscriptions {
+    pub fn new(linter: Arc<Linter>) -> Self {
+        Self {
+            inner: Default::default(),
+            linter,
+            retention: Retention::default(),
+            record_all: None,
+            allowlist: Allowlist::default(),
+            tls: Default::default(),
+        }
+    }
+
+    /// Set how long dropped tasks, resources and async ops are kept around.
+    pub fn retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Record every subscription, for as long as it is open.
+    pub fn record_all(mut self, recorder: Recorder) -> Self {
+        self.record_all = Some(recorder);
+        self
+    }
+
+    /// Only connect to consoles `allowlist` allows, checked every time a connection is made.
+    pub fn allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Connect to the console at `addr` over TLS, or over plaintext if `tls` is `None`.
+    ///
+    /// Takes effect the next time a connection to `addr` is opened.
+    pub fn set_tls(&self, addr: ConsoleAddr, tls: Option<TlsOptions>) {
+        let mut map = self.tls.lock();
+        match tls {
+            Some(tls) => map.insert(addr, tls),
+            None => map.remove(&addr),
+        };
+    }
+
+    /// Get the state of the console at `addr`, connecting to it if nobody else is watching it.
+    ///
+    /// The connection is supervised and automatically re-established with exponential backoff if
+    /// it fails. Its status is available in [`ConsoleState::connection`].
     pub async fn subscribe(&self, addr: ConsoleAddr) -> anyhow::Result<ConsoleStateWatch> {
+        self.subscribe_with_tls(addr, None).await
+    }
+
+    /// Like [`ConsoleSubscriptions::subscribe`], but connects with `tls` rather than the options
+    /// given to [`ConsoleSubscriptions::set_tls`], if it is `Some`.
+    ///
+    /// Fails if there already is a subscription to `addr` using other options.
+    pub async fn subscribe_with_tls(
+        &self,
+        addr: ConsoleAddr,
+        tls: Option<TlsOptions>,
+    ) -> anyhow::Result<ConsoleStateWatch> {
         let map = self.inner.clone();
 
         match self.inner.lock().await.entry(addr.clone()) {
             Entry::Occupied(entry) => {
+                if let Some(tls) = &tls {
+                    anyhow::ensure!(
+                        entry.get().tls.as_ref() == Some(tls),
+                        "Already connected to {} with different TLS options",
+                        addr
+                    );
+                }
+
                 tracing::debug!(?addr, "reusing existing subscription");
-                Ok(entry.get().clone())
+                Ok(entry.get().watch.clone())
             }
             Entry::Vacant(entry) => {
-                let endpoint = format!("http://{}:{}", addr.ip, addr.port).parse::<Endpoint>()?;
+                // a lazy channel doesn't connect until it's used and reconnects when needed
+                let tls = tls.or_else(|| self.tls.lock().get(&addr).cloned());
+                let client =
+                    InstrumentClient::new(addr.connect_lazy(tls.as_ref(), &self.allowlist)?);
+
+                let (pipeline, watch) = StatePipeline::new(
+                    Some(client.clone()),
+                    Arc::clone(&self.linter),
+                    self.retention,
+                );
+
+                if let Some(recorder) = &self.record_all {
+                    if let Err(err) = recorder.start(addr.clone(), &watch).await {
+                        tracing::error!(%err, ?addr, "failed to start recording");
+                    }
+                }
 
-                let channel = endpoint.connect().await?;
-                let mut client = InstrumentClient::new(channel);
+                let disconnect = Arc::new(Notify::new());
 
-                let stream = client
-                    .watch_updates(InstrumentRequest {})
-                    .await?
-                    .into_inner();
+                tokio::spawn({
+                    let disconnect = Arc::clone(&disconnect);
+                    async move {
+                        tracing::debug!(?addr, "creating subscription for");
+                        let registration = Registration::new(map, addr);
+                        let mut pipeline = pipeline;
 
-                let (tx, rx) = watch::channel(ConsoleState::default());
+                        let mut resume_client = client.clone();
 
-                tokio::spawn(async move {
-                    tracing::debug!(?addr, "creating subscription for");
-                    match subscribe_to_console_updates(stream, tx).await {
-                        Ok(()) => {
-                            tracing::debug!(?addr, "watch stream ended");
-                        }
-                        Err(err) => {
-                            tracing::error!(%err, "console watch stream ended");
+                        let disconnected = tokio::select! {
+                            _ = supervise_subscription(client, &mut pipeline, &registration) => false,
+                            _ = disconnect.notified() => true,
+                        };
+
+                        if disconnected {
+                            tracing::debug!(addr = ?registration.addr, "disconnected");
+                            if pipeline.state.remote_paused {
+                                resume(&mut resume_client, &mut pipeline).await;
+                            }
+                            let _ = pipeline.set_connection(ConnectionStatus::Disconnected);
                         }
+
+                        registration.deregister().await;
+                        pipeline.end_raw_updates();
+                        tracing::debug!(addr = ?registration.addr, "subscription ended");
+
+                        // keep the final status around for whoever is still watching
+                        pipeline.closed().await;
                     }
-                    map.lock().await.remove(&addr);
                 });
 
-                let watch = ConsoleStateWatch { rx };
-                entry.insert(watch.clone());
+                entry.insert(Subscription {
+                    watch: watch.clone(),
+                    disconnect,
+                    tls,
+                });
                 Ok(watch)
             }
         }
     }
+
+    /// Stay subscribed to the console at `addr` for as long as the process runs, starting over
+    /// whenever the subscription gives up.
+    pub fn keep_subscribed(&self, addr: ConsoleAddr) {
+        let subscriptions = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match subscriptions.subscribe(addr.clone()).await {
+                    Ok(mut watch) => {
+                        while watch.changed().await.is_ok() {
+                            if matches!(
+                                watch.borrow().connection,
+                                ConnectionStatus::GaveUp { .. } | ConnectionStatus::Disconnected
+                            ) {
+                                break;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        tracing::error!(%err, ?addr, "failed to subscribe");
+                    }
+                }
+
+                tokio::time::sleep(Backoff::default().max).await;
+            }
+        });
+    }
+
+    /// All open subscriptions.
+    pub async fn active(&self) -> Vec<(ConsoleAddr, ConsoleStateWatch)> {
+        self.inner
+            .lock()
+            .await
+            .iter()
+            .map(|(addr, subscription)| (addr.clone(), subscription.watch.clone()))
+            .collect()
+    }
+
+    /// Close the connection to the console at `addr`. Returns whether there was one.
+    ///
+    /// Anyone still watching keeps the last known state.
+    pub async fn disconnect(&self, addr: &ConsoleAddr) -> bool {
+        match self.inner.lock().await.get(addr) {
+            Some(subscription) => {
+                subscription.disconnect.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
 }
 
-async fn subscribe_to_console_updates(
-    update_stream: Streaming<console_api::instrument::Update>,
+struct Subscription {
+    watch: ConsoleStateWatch,
+    disconnect: Arc<Notify>,
+    /// What the connection was opened with.
+    tls: Option<TlsOptions>,
+}
+
+/// A subscription's entry in [`ConsoleSubscriptions`].
+///
+/// Subscriptions must remove themselves when they end so the next call to
+/// [`ConsoleSubscriptions::subscribe`] creates a new connection rather than returning a dead one.
+struct Registration {
+    map: SubscriptionMap,
+    addr: ConsoleAddr,
+    registered: AtomicBool,
+}
+
+impl Registration {
+    fn new(map: SubscriptionMap, addr: ConsoleAddr) -> Self {
+        Self {
+            map,
+            addr,
+            registered: AtomicBool::new(true),
+        }
+    }
+
+    /// Deregister if nobody is watching `pipeline` anymore. Returns whether we deregistered.
+    async fn deregister_if_unused(&self, pipeline: &StatePipeline) -> bool {
+        // hold the lock while checking so nobody can subscribe in the meantime
+        let mut map = self.map.lock().await;
+
+        // there will always be one receiver in the map
+        // so if there is only 1, then all others are gone
+        if pipeline.receiver_count() == 1 {
+            self.remove(&mut map);
+            true
+        } else {
+            false
+        }
+    }
+
+    async fn deregister(&self) {
+        let mut map = self.map.lock().await;
+        self.remove(&mut map);
+    }
+
+    fn remove(&self, map: &mut HashMap<ConsoleAddr, Subscription>) {
+        // only remove our own entry, a new subscription might have been created since
+        if self.registered.swap(false, Ordering::SeqCst) {
+            map.remove(&self.addr);
+        }
+    }
+}
+
+const RAW_UPDATES_CAPACITY: usize = 128;
+
+/// Applies updates to a [`ConsoleState`] and publishes the result to a [`ConsoleStateWatch`].
+pub(crate) struct StatePipeline {
+    state: ConsoleState,
     tx: watch::Sender<ConsoleState>,
-) -> anyhow::Result<()> {
-    let mut state = ConsoleState::default();
+    updates: RawUpdates,
+    linter: Arc<Linter>,
+    retention: Retention,
+    remote: mpsc::Receiver<RemoteRequest>,
+    viewers: Arc<AtomicUsize>,
+    /// The next update starts over from nothing, see [`StatePipeline::reset`].
+    starting_over: bool,
+}
+
+impl StatePipeline {
+    pub(crate) fn new(
+        client: Option<InstrumentClient>,
+        linter: Arc<Linter>,
+        retention: Retention,
+    ) -> (Self, ConsoleStateWatch) {
+        let (tx, rx) = watch::channel(ConsoleState::default());
+        let (updates, _) = broadcast::channel(RAW_UPDATES_CAPACITY);
+        let updates = Arc::new(parking_lot::Mutex::new(Some(updates)));
+        let (remote_tx, remote) = mpsc::channel(1);
+        let viewers = Arc::new(AtomicUsize::new(0));
+
+        let watch = ConsoleStateWatch {
+            rx,
+            // only live consoles can be paused
+            remote: client.as_ref().map(|_| remote_tx),
+            client,
+            updates: Arc::clone(&updates),
+            viewers: Arc::clone(&viewers),
+        };
+
+        let pipeline = Self {
+            state: ConsoleState::default(),
+            tx,
+            updates,
+            linter,
+            retention,
+            remote,
+            viewers,
+            starting_over: true,
+        };
+
+        (pipeline, watch)
+    }
+
+    /// The number of [`ConsoleStateWatch`]es still receiving state from this pipeline.
+    pub(crate) fn receiver_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// The number of [`Viewer`]s of this pipeline's state.
+    pub(crate) fn viewer_count(&self) -> usize {
+        self.viewers.load(Ordering::SeqCst)
+    }
+
+    /// Wait until all [`ConsoleStateWatch`]es have been dropped.
+    pub(crate) async fn closed(&self) {
+        self.tx.closed().await
+    }
+
+    /// Apply an update and notify subscribers.
+    pub(crate) fn push(&mut self, update: console_api::instrument::Update) -> anyhow::Result<()> {
+        self.apply(update)?;
+        self.publish()
+    }
+
+    /// Apply an update without notifying subscribers.
+    pub(crate) fn apply(&mut self, update: console_api::instrument::Update) -> anyhow::Result<()> {
+        if let Some(updates) = &*self.updates.lock() {
+            if updates.receiver_count() != 0 {
+                // an error only means that all receivers went away in the meantime
+                let _ = updates.send(Arc::new(RawUpdate {
+                    update: update.clone(),
+                    snapshot: self.starting_over,
+                }));
+            }
+        }
+        self.starting_over = false;
+
+        self.state.apply_update(update, &self.retention)
+    }
+
+    /// Stop sending raw updates, which closes the channel for everyone receiving them.
+    pub(crate) fn end_raw_updates(&self) {
+        self.updates.lock().take();
+    }
+
+    /// Throw away all state, as if no updates had been applied.
+    pub(crate) fn reset(&mut self) {
+        self.state = ConsoleState {
+            connection: self.state.connection.clone(),
+            // a new stream doesn't undo pausing
+            remote_paused: self.state.remote_paused,
+            ..Default::default()
+        };
+        self.starting_over = true;
+    }
+
+    /// Change the connection status and notify subscribers.
+    pub(crate) fn set_connection(&mut self, connection: ConnectionStatus) -> anyhow::Result<()> {
+        self.state.connection = connection;
+        self.publish()
+    }
+
+    pub(crate) fn publish(&mut self) -> anyhow::Result<()> {
+        self.state.warnings = self.linter.check(&self.state);
+
+        self.tx
+            .send(self.state.clone())
+            .map_err(|_| anyhow::Error::msg("failed to send new state"))
+    }
+}
+
+impl Drop for StatePipeline {
+    fn drop(&mut self) {
+        self.end_raw_updates();
+    }
+}
+
+/// An update as it was received from the console, see [`ConsoleStateWatch::raw_updates`].
+#[derive(Debug)]
+pub struct RawUpdate {
+    pub update: console_api::instrument::Update,
+    /// This is the first update of a stream, which contains everything the console knows about.
+    pub snapshot: bool,
+}
+
+/// The state of the connection to a console.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Live,
+    Retrying {
+        delay: Duration,
+        error: String,
+    },
+    GaveUp {
+        error: String,
+    },
+    /// The state comes from a recording rather than a live console.
+    Replay,
+    /// Someone closed the connection.
+    Disconnected,
+}
+
+impl Default for ConnectionStatus {
+    fn default() -> Self {
+        ConnectionStatus::Connecting
+    }
+}
+
+impl ConnectionStatus {
+    /// Whether the state is being kept up to date. If not then it shows the last known state.
+    pub fn is_stale(&self) -> bool {
+        !matches!(self, ConnectionStatus::Live | ConnectionStatus::Replay)
+    }
+}
+
+impl fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionStatus::Connecting => write!(f, "connecting..."),
+            ConnectionStatus::Live => write!(f, "live"),
+            ConnectionStatus::Retrying { delay, error } => {
+                write!(f, "retrying in {}s ({})", delay.as_secs(), error)
+            }
+            ConnectionStatus::GaveUp { error } => write!(f, "gave up ({})", error),
+            ConnectionStatus::Replay => write!(f, "replay"),
+            ConnectionStatus::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
+
+/// Exponential backoff used when reconnecting to a console.
+struct Backoff {
+    attempts: u32,
+    initial: Duration,
+    max: Duration,
+    max_attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            attempts: 0,
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(30),
+            max_attempts: 10,
+        }
+    }
+}
+
+impl Backoff {
+    /// How long to wait before the next attempt, or `None` if we should give up.
+    fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempts >= self.max_attempts {
+            return None;
+        }
+
+        let delay = self
+            .initial
+            .checked_mul(2_u32.saturating_pow(self.attempts))
+            .map_or(self.max, |delay| delay.min(self.max));
+        self.attempts += 1;
+        Some(delay)
+    }
+
+    fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Keep `pipeline` fed with updates from `client`, reconnecting whenever the stream fails.
+///
+/// Returns once nobody is watching anymore or when we've given up reconnecting.
+async fn supervise_subscription(
+    mut client: InstrumentClient,
+    pipeline: &mut StatePipeline,
+    registration: &Registration,
+) {
+    let mut backoff = Backoff::default();
+
+    loop {
+        if let Err(err) = pipeline.set_connection(ConnectionStatus::Connecting) {
+            tracing::error!(%err, "failed to publish connection status");
+            break;
+        }
+
+        let result = match client.watch_updates(InstrumentRequest {}).await {
+            Ok(stream) => {
+                backoff.reset();
+                let stream = stream.into_inner();
+                subscribe_to_console_updates(stream, &mut client, pipeline, registration).await
+            }
+            Err(err) => Err(err.into()),
+        };
+
+        let error = match result {
+            Ok(StreamEnd::NoReceivers) => break,
+            Ok(StreamEnd::Closed) => "stream closed".to_owned(),
+            // include the causes, such as why a TLS handshake failed, and not just "transport error"
+            Err(err) => format!("{:#}", err),
+        };
+
+        let delay = match backoff.next_delay() {
+            Some(delay) => delay,
+            None => {
+                tracing::warn!(%error, "console subscription failed, giving up");
+                let _ = pipeline.set_connection(ConnectionStatus::GaveUp { error });
+                break;
+            }
+        };
+
+        tracing::warn!(%error, ?delay, "console subscription failed, retrying");
+        if pipeline
+            .set_connection(ConnectionStatus::Retrying { delay, error })
+            .is_err()
+        {
+            break;
+        }
+
+        tokio::time::sleep(delay).await;
+
+        if registration.deregister_if_unused(pipeline).await {
+            tracing::debug!("no more receivers on stream, not reconnecting");
+            break;
+        }
+    }
+}
+
+/// Why [`subscribe_to_console_updates`] stopped.
+enum StreamEnd {
+    NoReceivers,
+    Closed,
+}
 
+async fn subscribe_to_console_updates(
+    update_stream: Streaming<console_api::instrument::Update>,
+    client: &mut InstrumentClient,
+    pipeline: &mut StatePipeline,
+    registration: &Registration,
+) -> anyhow::Result<StreamEnd> {
     #[allow(clippy::large_enum_variant)]
     enum Msg {
-        Update(console_api::instrument::Update),
+        Update(Result<console_api::instrument::Update, tonic::Status>),
+        StreamEnded,
         CheckReceivers,
+        Remote(RemoteRequest),
     }
 
     let freq = Duration::from_secs(10);
@@ -80,133 +582,101 @@ async fn subscribe_to_console_updates(
         IntervalStream::new(tokio::time::interval_at(Instant::now() + freq, freq))
             .map(|_| Msg::CheckReceivers);
 
-    let update_stream = update_stream.filter_map(Result::ok).map(Msg::Update);
+    // the interval never ends, so the merged stream wouldn't either without this marker
+    let update_stream = update_stream
+        .map(Msg::Update)
+        .chain(tokio_stream::once(Msg::StreamEnded));
 
     let mut stream = update_stream.merge(check_receivers_interval);
 
-    while let Some(msg) = stream.next().await {
+    let mut first_update = true;
+
+    loop {
+        let msg = tokio::select! {
+            msg = stream.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            Some(request) = pipeline.remote.recv() => Msg::Remote(request),
+        };
+
         match msg {
             Msg::CheckReceivers => {
-                if tx.receiver_count() == 1 {
-                    // there will always be one receiver in the map
-                    // so if there is only 1, then all others are gone
+                if registration.deregister_if_unused(pipeline).await {
                     tracing::debug!("no more receivers on stream, closing");
-                    break;
-                }
-            }
-            Msg::Update(msg) => {
-                #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
-                pub struct Update {
-                    new_tasks: Vec<Task>,
-                    stats_update: BTreeMap<TaskId, TaskStats>,
-                    new_metadata: HashMap<MetaId, Metadata>,
+                    if pipeline.state.remote_paused {
+                        resume(client, pipeline).await;
+                    }
+                    return Ok(StreamEnd::NoReceivers);
                 }
 
-                let console_api::instrument::Update {
-                    task_update,
-                    new_metadata,
-                    resource_update,
-                    ..
-                } = msg;
-
-                // update metadata
-                for new_metadata in new_metadata.unwrap_or_default().metadata {
-                    let metadata = Metadata::try_from(new_metadata)?;
-                    state.metadata.insert(metadata.id, metadata);
+                // checked on this interval, rather than as soon as a view goes away, so going
+                // from one page to another doesn't resume the console
+                if pipeline.state.remote_paused && pipeline.viewer_count() == 0 {
+                    tracing::debug!("nobody is viewing the paused console, resuming");
+                    resume(client, pipeline).await;
                 }
-
-                // update tasks
-                {
-                    let console_api::tasks::TaskUpdate {
-                        new_tasks,
-                        mut stats_update,
-                        dropped_events: _,
-                    } = task_update.context("Missing `task_update` field")?;
-
-                    for new_task in new_tasks {
-                        let id = new_task.id;
-                        let mut task = Task::try_from(new_task)?;
-
-                        if let Some(id) = id {
-                            if let Some(stats) = stats_update.remove(&id.id) {
-                                let stats = TaskStats::try_from(stats)?;
-                                task.stats = Some(stats);
-                            }
-                        }
-
-                        if let Some(metadata) = state.metadata.get(&task.metadata_id) {
-                            task.target = Some(metadata.target.clone());
-                        }
-
-                        state.tasks.insert(task.id, Arc::new(task));
-                    }
-
-                    state.tasks.retain(|_id, task| {
-                        if let Some(stats) = &task.stats {
-                            if let Some(dropped_at) = stats.dropped_at {
-                                dropped_at.elapsed().unwrap() < Duration::from_secs(5)
-                            } else {
-                                true
-                            }
-                        } else {
-                            true
-                        }
-                    });
+            }
+            Msg::Remote(RemoteRequest { paused, reply }) => {
+                let result = set_remote_paused(client, pipeline, paused).await;
+                // the requester might have given up waiting
+                let _ = reply.send(result);
+            }
+            Msg::Update(msg) => {
+                let msg = msg?;
+                if first_update {
+                    // the first update of a new stream contains everything the console knows
+                    // about, so start over in case the process was restarted
+                    first_update = false;
+                    pipeline.reset();
+                    pipeline.state.connection = ConnectionStatus::Live;
+                    pipeline.state.connected_at = Some(SystemTime::now());
                 }
+                pipeline.push(msg)?;
+            }
+            Msg::StreamEnded => break,
+        }
+    }
 
-                // update resources
-                {
-                    let console_api::resources::ResourceUpdate {
-                        new_resources,
-                        mut stats_update,
-                        new_poll_ops: _,
-                        dropped_events: _,
-                    } = resource_update.context("Missing `resource_update` field")?;
-
-                    for new_resource in new_resources {
-                        let id = new_resource.id;
-                        let mut resource = Resource::try_from(new_resource)?;
-
-                        if let Some(id) = id {
-                            if let Some(stats) = stats_update.remove(&id.id) {
-                                let stats = ResourceStats::try_from(stats)?;
-                                resource.stats = Some(stats);
-                            }
-                        }
-
-                        if let Some(metadata) = state.metadata.get(&resource.metadata_id) {
-                            resource.target = Some(metadata.target.clone());
-                        }
+    Ok(StreamEnd::Closed)
+}
 
-                        state.resources.insert(resource.id, Arc::new(resource));
-                    }
+/// Ask the console to stop or start sending updates, to everyone subscribed to it.
+async fn set_remote_paused(
+    client: &mut InstrumentClient,
+    pipeline: &mut StatePipeline,
+    paused: bool,
+) -> anyhow::Result<()> {
+    if paused {
+        client.pause(PauseRequest {}).await?;
+    } else {
+        client.resume(ResumeRequest {}).await?;
+    }
 
-                    state.resources.retain(|_id, resource| {
-                        if let Some(stats) = &resource.stats {
-                            if let Some(dropped_at) = stats.dropped_at {
-                                dropped_at.elapsed().unwrap() < Duration::from_secs(5)
-                            } else {
-                                true
-                            }
-                        } else {
-                            true
-                        }
-                    });
-                }
+    pipeline.state.remote_paused = paused;
+    pipeline.publish()
+}
 
-                // notify subscribers
-                tx.send(state.clone())
-                    .map_err(|_| anyhow::Error::msg("failed to send new state"))?;
-            }
-        }
+/// Resume a paused console because nobody is watching it anymore.
+async fn resume(client: &mut InstrumentClient, pipeline: &mut StatePipeline) {
+    if let Err(err) = set_remote_paused(client, pipeline, false).await {
+        tracing::warn!(%err, "failed to resume console");
     }
+}
 
-    Ok(())
+/// A request for [`set_remote_paused`], sent from a [`ConsoleStateWatch`].
+struct RemoteRequest {
+    paused: bool,
+    reply: oneshot::Sender<anyhow::Result<()>>,
 }
 
 #[derive(Clone)]
 pub struct ConsoleStateWatch {
     rx: watch::Receiver<ConsoleState>,
+    client: Option<InstrumentClient>,
+    updates: RawUpdates,
+    remote: Option<mpsc::Sender<RemoteRequest>>,
+    viewers: Arc<AtomicUsize>,
 }
 
 impl ConsoleStateWatch {
@@ -217,13 +687,355 @@ impl ConsoleStateWatch {
     pub async fn changed(&mut self) -> anyhow::Result<()> {
         Ok(self.rx.changed().await?)
     }
+
+    /// Wait until the first attempt at connecting has either succeeded or failed.
+    pub async fn connected(&mut self) -> anyhow::Result<()> {
+        loop {
+            match &self.borrow().connection {
+                ConnectionStatus::Connecting => {}
+                ConnectionStatus::Live | ConnectionStatus::Replay => return Ok(()),
+                ConnectionStatus::Retrying { error, .. } | ConnectionStatus::GaveUp { error } => {
+                    anyhow::bail!("{}", error)
+                }
+                ConnectionStatus::Disconnected => anyhow::bail!("disconnected"),
+            }
+
+            self.changed().await?;
+        }
+    }
+
+    /// Ask the console to stop or start sending updates. Unlike pausing a view this affects
+    /// everyone watching the console, which is shown by [`ConsoleState::remote_paused`].
+    ///
+    /// The console is resumed automatically once it has no [`Viewer`]s.
+    pub async fn set_remote_paused(&self, paused: bool) -> anyhow::Result<()> {
+        let remote = self
+            .remote
+            .as_ref()
+            .context("Only live consoles can be paused")?;
+        anyhow::ensure!(
+            self.borrow().connection == ConnectionStatus::Live,
+            "The console is not connected"
+        );
+
+        let (reply, rx) = oneshot::channel();
+        remote
+            .send(RemoteRequest { paused, reply })
+            .await
+            .map_err(|_| anyhow::Error::msg("The subscription has ended"))?;
+        rx.await.context("The subscription has ended")?
+    }
+
+    /// Count as someone viewing the console until the returned [`Viewer`] is dropped.
+    pub fn viewer(&self) -> Viewer {
+        self.viewers.fetch_add(1, Ordering::SeqCst);
+        Viewer(Arc::clone(&self.viewers))
+    }
+
+    /// Receive the raw updates sent by the console, before they're applied to the state.
+    ///
+    /// The receiver is closed once the subscription ends. It doesn't keep the subscription open.
+    pub fn raw_updates(&self) -> anyhow::Result<broadcast::Receiver<Arc<RawUpdate>>> {
+        let updates = self.updates.lock();
+        let updates = updates.as_ref().context("The subscription has ended")?;
+        Ok(updates.subscribe())
+    }
+
+    /// Ask the console for everything it currently knows about, as a single update.
+    ///
+    /// This opens a separate stream, so the subscription isn't affected.
+    pub async fn snapshot(&self) -> anyhow::Result<console_api::instrument::Update> {
+        let mut client = self
+            .client
+            .clone()
+            .context("Snapshots are only available for live consoles")?;
+
+        let mut stream = client
+            .watch_updates(InstrumentRequest {})
+            .await?
+            .into_inner();
+
+        stream
+            .message()
+            .await?
+            .context("The console closed the stream without sending anything")
+    }
+
+    pub async fn watch_task_details(
+        &self,
+        id: TaskId,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<TaskDetails>> + Send + Unpin + 'static>
+    {
+        let mut client = self
+            .client
+            .clone()
+            .context("Task details are only available for live consoles")?;
+
+        let stream = client
+            .watch_task_details(TaskDetailsRequest {
+                id: Some(console_api::Id { id: id.0 }),
+            })
+            .await?
+            .into_inner();
+
+        Ok(stream.map(|details| TaskDetails::try_from(details?)))
+    }
+}
+
+/// Someone looking at a console in the UI, see [`ConsoleStateWatch::viewer`].
+pub struct Viewer(Arc<AtomicUsize>);
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Default, Clone, Debug)]
 pub struct ConsoleState {
     pub tasks: BTreeMap<TaskId, Arc<Task>>,
     pub resources: BTreeMap<ResourceId, Arc<Resource>>,
+    pub async_ops: BTreeMap<AsyncOpId, Arc<AsyncOp>>,
+    pub poll_ops: BTreeMap<AsyncOpId, PollOp>,
     pub metadata: HashMap<MetaId, Metadata>,
+    pub warnings: BTreeMap<TaskId, Vec<Warning>>,
+    pub connection: ConnectionStatus,
+    /// The time of the latest update, according to the console's clock.
+    pub now: Option<SystemTime>,
+    /// When the current connection was established, according to our clock.
+    pub connected_at: Option<SystemTime>,
+    /// The number of updates received since connecting.
+    pub update_count: u64,
+    /// The console was asked to stop sending updates, to everyone watching it.
+    pub remote_paused: bool,
+}
+
+impl ConsoleState {
+    /// How long ago `time` was, according to the console's clock.
+    ///
+    /// Times in the future count as just now, since the clocks involved might disagree slightly.
+    pub fn elapsed_since(&self, time: SystemTime) -> Option<Duration> {
+        Some(self.now?.duration_since(time).unwrap_or_default())
+    }
+
+    fn apply_update(
+        &mut self,
+        update: console_api::instrument::Update,
+        retention: &Retention,
+    ) -> anyhow::Result<()> {
+        let console_api::instrument::Update {
+            now,
+            task_update,
+            new_metadata,
+            resource_update,
+            async_op_update,
+        } = update;
+
+        self.update_count += 1;
+
+        // the console's clock might not agree with ours, so all ages are based on its time
+        if let Some(now) = now {
+            self.now = Some(SystemTime::try_from(now)?);
+        }
+
+        // update metadata
+        for new_metadata in new_metadata.unwrap_or_default().metadata {
+            let metadata = Metadata::try_from(new_metadata)?;
+            self.metadata.insert(metadata.id, metadata);
+        }
+
+        // update tasks
+        {
+            let console_api::tasks::TaskUpdate {
+                new_tasks,
+                stats_update,
+                dropped_events: _,
+            } = task_update.context("Missing `task_update` field")?;
+
+            for new_task in new_tasks {
+                let mut task = Task::try_from(new_task)?;
+
+                if let Some(metadata) = self.metadata.get(&task.metadata_id) {
+                    task.target = Some(metadata.target.clone());
+                }
+
+                self.tasks.insert(task.id, Arc::new(task));
+            }
+
+            // stats are sent for new tasks and for every task whose stats changed since the
+            // last update, so merge them into whatever tasks we already know about
+            for (id, stats) in stats_update {
+                if let Some(task) = self.tasks.get_mut(&TaskId(id)) {
+                    let stats = TaskStats::try_from(stats)?;
+                    Arc::make_mut(task).stats = Some(stats);
+                }
+            }
+
+            retention.retain(self.now, &mut self.tasks, |task| {
+                task.stats.as_ref().and_then(|stats| stats.dropped_at)
+            });
+        }
+
+        // update resources
+        {
+            let console_api::resources::ResourceUpdate {
+                new_resources,
+                stats_update,
+                new_poll_ops,
+                dropped_events: _,
+            } = resource_update.context("Missing `resource_update` field")?;
+
+            for new_resource in new_resources {
+                let mut resource = Resource::try_from(new_resource)?;
+
+                if let Some(metadata) = self.metadata.get(&resource.metadata_id) {
+                    resource.target = Some(metadata.target.clone());
+                }
+
+                self.resources.insert(resource.id, Arc::new(resource));
+            }
+
+            for (id, stats) in stats_update {
+                if let Some(resource) = self.resources.get_mut(&ResourceId(id)) {
+                    let stats = ResourceStats::try_from(stats)?;
+                    Arc::make_mut(resource).stats = Some(stats);
+                }
+            }
+
+            retention.retain(self.now, &mut self.resources, |resource| {
+                resource.stats.as_ref().and_then(|stats| stats.dropped_at)
+            });
+
+            // only the most recent poll of each async op matters for figuring out who is
+            // waiting on what
+            for poll_op in new_poll_ops {
+                let poll_op = PollOp::try_from(poll_op)?;
+                self.poll_ops.insert(poll_op.async_op_id, poll_op);
+            }
+
+            let (tasks, resources) = (&self.tasks, &self.resources);
+            self.poll_ops.retain(|_id, poll_op| {
+                tasks.contains_key(&poll_op.task_id) && resources.contains_key(&poll_op.resource_id)
+            });
+        }
+
+        // update async ops
+        {
+            let console_api::async_ops::AsyncOpUpdate {
+                new_async_ops,
+                stats_update,
+                dropped_events: _,
+            } = async_op_update.context("Missing `async_op_update` field")?;
+
+            for new_async_op in new_async_ops {
+                let mut async_op = AsyncOp::try_from(new_async_op)?;
+
+                if let Some(metadata) = self.metadata.get(&async_op.metadata_id) {
+                    async_op.target = Some(metadata.target.clone());
+                }
+
+                self.async_ops.insert(async_op.id, Arc::new(async_op));
+            }
+
+            for (id, stats) in stats_update {
+                if let Some(async_op) = self.async_ops.get_mut(&AsyncOpId(id)) {
+                    let stats = AsyncOpStats::try_from(stats)?;
+                    Arc::make_mut(async_op).stats = Some(stats);
+                }
+            }
+
+            retention.retain(self.now, &mut self.async_ops, |async_op| {
+                async_op.stats.as_ref().and_then(|stats| stats.dropped_at)
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// How long completed tasks, dropped resources and dropped async ops are kept around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Retention {
+    /// Keep them for this long after they were dropped.
+    Duration(Duration),
+    /// Keep this many of the most recently dropped.
+    Count(usize),
+    Forever,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention::Duration(Duration::from_secs(5))
+    }
+}
+
+impl Retention {
+    fn retain<K, V>(
+        &self,
+        now: Option<SystemTime>,
+        map: &mut BTreeMap<K, Arc<V>>,
+        dropped_at: impl Fn(&V) -> Option<SystemTime>,
+    ) where
+        K: Ord + Copy,
+    {
+        match *self {
+            Retention::Duration(retention) => {
+                map.retain(|_, value| match dropped_at(value).zip(now) {
+                    Some((dropped_at, now)) => now
+                        .duration_since(dropped_at)
+                        .map_or(true, |age| age < retention),
+                    None => true,
+                });
+            }
+            Retention::Count(max) => {
+                let mut dropped = map
+                    .iter()
+                    .filter_map(|(key, value)| Some((dropped_at(value)?, *key)))
+                    .collect::<Vec<_>>();
+
+                if dropped.len() > max {
+                    // most recently dropped first
+                    dropped.sort_unstable_by(|a, b| b.cmp(a));
+                    for (_, key) in &dropped[max..] {
+                        map.remove(key);
+                    }
+                }
+            }
+            Retention::Forever => {}
+        }
+    }
+}
+
+impl FromStr for Retention {
+    type Err = anyhow::Error;
+
+    /// Parses `forever`, a count such as `100` or a duration such as `500ms`, `30s`, `5m` or `1h`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "forever" {
+            return Ok(Retention::Forever);
+        }
+
+        if let Ok(count) = s.parse() {
+            return Ok(Retention::Count(count));
+        }
+
+        let split_at = s
+            .find(|c: char| !c.is_ascii_digit())
+            .context("Invalid retention")?;
+        let (value, unit) = s.split_at(split_at);
+        let value = value.parse::<u64>().context("Invalid retention")?;
+
+        let duration = match unit {
+            "ms" => Some(Duration::from_millis(value)),
+            "s" => Some(Duration::from_secs(value)),
+            "m" => value.checked_mul(60).map(Duration::from_secs),
+            "h" => value.checked_mul(60 * 60).map(Duration::from_secs),
+            _ => anyhow::bail!("Invalid retention unit `{}`, expected ms, s, m or h", unit),
+        }
+        .with_context(|| format!("Retention `{}` is too long", s))?;
+
+        Ok(Retention::Duration(duration))
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -301,22 +1113,7 @@ impl TryFrom<console_api::tasks::Task> for Task {
 
         let fields = fields
             .into_iter()
-            .map(|field| {
-                let name = field.name.context("Missing `name` field")?;
-                let value = field.value.context("Missing `value` field")?;
-
-                let name = match name {
-                    console_api::field::Name::StrName(name) => name,
-                    console_api::field::Name::NameIdx(_) => {
-                        tracing::warn!("hit NameIdx");
-                        return Ok(None);
-                    }
-                };
-
-                let value = FieldValue::from(value);
-
-                Ok(Some((name, value)))
-            })
+            .map(parse_field)
             .filter_map(Result::transpose)
             .collect::<anyhow::Result<_>>()?;
 
@@ -358,6 +1155,23 @@ impl TryFrom<console_api::register_metadata::NewMetadata> for Metadata {
     }
 }
 
+fn parse_field(field: console_api::Field) -> anyhow::Result<Option<(String, FieldValue)>> {
+    let name = field.name.context("Missing `name` field")?;
+    let value = field.value.context("Missing `value` field")?;
+
+    let name = match name {
+        console_api::field::Name::StrName(name) => name,
+        console_api::field::Name::NameIdx(_) => {
+            tracing::warn!("hit NameIdx");
+            return Ok(None);
+        }
+    };
+
+    let value = FieldValue::from(value);
+
+    Ok(Some((name, value)))
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum FieldValue {
     Debug(String),
@@ -399,6 +1213,11 @@ pub struct TaskStats {
     pub last_poll_started: Option<Duration>,
     pub last_poll_ended: Option<Duration>,
     pub polls: u64,
+    pub wakes: u64,
+    pub waker_clones: u64,
+    pub waker_drops: u64,
+    pub last_wake: Option<SystemTime>,
+    pub self_wakes: u64,
 }
 
 impl TryFrom<console_api::tasks::Stats> for TaskStats {
@@ -408,16 +1227,17 @@ impl TryFrom<console_api::tasks::Stats> for TaskStats {
         let console_api::tasks::Stats {
             created_at,
             dropped_at,
-            wakes: _,
-            waker_clones: _,
-            waker_drops: _,
-            last_wake: _,
-            self_wakes: _,
+            wakes,
+            waker_clones,
+            waker_drops,
+            last_wake,
+            self_wakes,
             poll_stats,
         } = stats;
 
         let created_at = created_at.map(SystemTime::try_from).transpose()?;
         let dropped_at = dropped_at.map(SystemTime::try_from).transpose()?;
+        let last_wake = last_wake.map(SystemTime::try_from).transpose()?;
 
         let poll_stats = poll_stats.context("Missing `poll_stats` field")?;
 
@@ -441,15 +1261,84 @@ impl TryFrom<console_api::tasks::Stats> for TaskStats {
             busy_time,
             last_poll_started,
             last_poll_ended,
+            wakes,
+            waker_clones,
+            waker_drops,
+            last_wake,
+            self_wakes,
         })
     }
 }
 
 impl TaskStats {
-    pub fn idle_time(&self) -> Option<Duration> {
-        let created_at = self.created_at?.elapsed().ok()?;
-        let busy_time = self.busy_time?;
-        Some(created_at - busy_time)
+    /// How long this has existed without being polled, as of `now` on the console's clock.
+    pub fn idle_time(&self, now: SystemTime) -> Option<Duration> {
+        let age = now.duration_since(self.created_at?).unwrap_or_default();
+        Some(age.saturating_sub(self.busy_time?))
+    }
+
+    /// The number of wakers currently referencing the task.
+    pub fn waker_count(&self) -> u64 {
+        self.waker_clones.saturating_sub(self.waker_drops)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct TaskDetails {
+    pub task_id: TaskId,
+    pub poll_times: Option<PollTimes>,
+}
+
+impl TryFrom<console_api::tasks::TaskDetails> for TaskDetails {
+    type Error = anyhow::Error;
+
+    fn try_from(details: console_api::tasks::TaskDetails) -> Result<Self, Self::Error> {
+        let task_id = TaskId(details.task_id.context("Missing `task_id` field")?.id);
+
+        let poll_times = details
+            .poll_times_histogram
+            .map(|bytes| PollTimes::from_histogram(&bytes))
+            .transpose()?;
+
+        Ok(Self {
+            task_id,
+            poll_times,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct PollTimes {
+    pub count: u64,
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    pub percentiles: Vec<(f64, Duration)>,
+}
+
+impl PollTimes {
+    const PERCENTILES: [f64; 7] = [10.0, 25.0, 50.0, 75.0, 90.0, 95.0, 99.0];
+
+    /// Decode a histogram of poll times, in nanoseconds, serialized in the HdrHistogram V2
+    /// format.
+    fn from_histogram(bytes: &[u8]) -> anyhow::Result<Self> {
+        let histogram: hdrhistogram::Histogram<u64> =
+            hdrhistogram::serialization::Deserializer::new()
+                .deserialize(&mut std::io::Cursor::new(bytes))
+                .context("Failed to deserialize poll times histogram")?;
+
+        let percentiles = Self::PERCENTILES
+            .iter()
+            .map(|&p| (p, Duration::from_nanos(histogram.value_at_percentile(p))))
+            .collect();
+
+        Ok(Self {
+            count: histogram.len(),
+            min: Duration::from_nanos(histogram.min()),
+            mean: Duration::from_nanos(histogram.mean() as u64),
+            max: Duration::from_nanos(histogram.max()),
+            percentiles,
+        })
     }
 }
 
@@ -483,6 +1372,12 @@ impl TryFrom<console_api::Location> for Location {
     }
 }
 
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
 fn truncate_registry_path(s: String) -> String {
     use once_cell::sync::OnceCell;
     use regex::Regex;
@@ -574,6 +1469,7 @@ pub enum TypeVisibility {
 pub struct ResourceStats {
     pub dropped_at: Option<SystemTime>,
     pub created_at: Option<SystemTime>,
+    pub attributes: Vec<Attribute>,
 }
 
 impl TryFrom<console_api::resources::Stats> for ResourceStats {
@@ -583,15 +1479,472 @@ impl TryFrom<console_api::resources::Stats> for ResourceStats {
         let console_api::resources::Stats {
             dropped_at,
             created_at,
-            attributes: _,
+            attributes,
         } = stats;
 
         let created_at = created_at.map(SystemTime::try_from).transpose()?;
         let dropped_at = dropped_at.map(SystemTime::try_from).transpose()?;
 
+        let attributes = attributes
+            .into_iter()
+            .map(Attribute::parse)
+            .filter_map(Result::transpose)
+            .collect::<anyhow::Result<_>>()?;
+
         Ok(Self {
             dropped_at,
             created_at,
+            attributes,
         })
     }
 }
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub value: FieldValue,
+    pub unit: Option<String>,
+}
+
+impl Attribute {
+    fn parse(attribute: console_api::Attribute) -> anyhow::Result<Option<Self>> {
+        let field = attribute.field.context("Missing `field` field")?;
+
+        Ok(parse_field(field)?.map(|(name, value)| Self {
+            name,
+            value,
+            unit: attribute.unit,
+        }))
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(unit) = &self.unit {
+            write!(f, "{}", unit)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AsyncOpId(pub u64);
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct AsyncOp {
+    pub id: AsyncOpId,
+    pub parent_id: Option<AsyncOpId>,
+    pub resource_id: ResourceId,
+    pub source: String,
+    pub metadata_id: MetaId,
+    pub target: Option<String>,
+    pub stats: Option<AsyncOpStats>,
+}
+
+impl AsyncOp {
+    /// The task currently awaiting this async op, if any.
+    pub fn task_id(&self) -> Option<TaskId> {
+        self.stats.as_ref()?.task_id
+    }
+}
+
+impl TryFrom<console_api::async_ops::AsyncOp> for AsyncOp {
+    type Error = anyhow::Error;
+
+    fn try_from(async_op: console_api::async_ops::AsyncOp) -> Result<Self, Self::Error> {
+        let id = AsyncOpId(async_op.id.context("Missing `id` field")?.id);
+
+        let metadata_id = MetaId(async_op.metadata.context("Missing `metadata` field")?.id);
+
+        let parent_id = async_op.parent_async_op_id.map(|id| AsyncOpId(id.id));
+
+        let resource_id = ResourceId(
+            async_op
+                .resource_id
+                .context("Missing `resource_id` field")?
+                .id,
+        );
+
+        Ok(Self {
+            id,
+            parent_id,
+            resource_id,
+            source: async_op.source,
+            metadata_id,
+            target: None,
+            stats: None,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct AsyncOpStats {
+    pub dropped_at: Option<SystemTime>,
+    pub created_at: Option<SystemTime>,
+    pub task_id: Option<TaskId>,
+    pub busy_time: Option<Duration>,
+    pub last_poll_started: Option<Duration>,
+    pub last_poll_ended: Option<Duration>,
+    pub polls: u64,
+    pub attributes: Vec<Attribute>,
+}
+
+impl TryFrom<console_api::async_ops::Stats> for AsyncOpStats {
+    type Error = anyhow::Error;
+
+    fn try_from(stats: console_api::async_ops::Stats) -> Result<Self, Self::Error> {
+        let created_at = stats.created_at.map(SystemTime::try_from).transpose()?;
+        let dropped_at = stats.dropped_at.map(SystemTime::try_from).transpose()?;
+
+        let task_id = stats.task_id.map(|id| TaskId(id.id));
+
+        let poll_stats = stats.poll_stats.context("Missing `poll_stats` field")?;
+
+        let polls = poll_stats.polls;
+        let busy_time = poll_stats
+            .busy_time
+            .map(|d| Duration::new(d.seconds as _, d.nanos as _));
+
+        let last_poll_started = poll_stats
+            .last_poll_started
+            .map(|d| Duration::new(d.seconds as _, d.nanos as _));
+
+        let last_poll_ended = poll_stats
+            .last_poll_ended
+            .map(|d| Duration::new(d.seconds as _, d.nanos as _));
+
+        let attributes = stats
+            .attributes
+            .into_iter()
+            .map(Attribute::parse)
+            .filter_map(Result::transpose)
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            dropped_at,
+            created_at,
+            task_id,
+            busy_time,
+            last_poll_started,
+            last_poll_ended,
+            polls,
+            attributes,
+        })
+    }
+}
+
+impl AsyncOpStats {
+    /// How long this has existed without being polled, as of `now` on the console's clock.
+    pub fn idle_time(&self, now: SystemTime) -> Option<Duration> {
+        let age = now.duration_since(self.created_at?).unwrap_or_default();
+        Some(age.saturating_sub(self.busy_time?))
+    }
+}
+
+/// The most recent poll of an async op on a resource.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct PollOp {
+    pub async_op_id: AsyncOpId,
+    pub resource_id: ResourceId,
+    pub task_id: TaskId,
+    pub metadata_id: MetaId,
+    pub name: String,
+    pub is_ready: bool,
+}
+
+impl TryFrom<console_api::resources::PollOp> for PollOp {
+    type Error = anyhow::Error;
+
+    fn try_from(poll_op: console_api::resources::PollOp) -> Result<Self, Self::Error> {
+        let async_op_id = AsyncOpId(
+            poll_op
+                .async_op_id
+                .context("Missing `async_op_id` field")?
+                .id,
+        );
+        let resource_id = ResourceId(
+            poll_op
+                .resource_id
+                .context("Missing `resource_id` field")?
+                .id,
+        );
+        let task_id = TaskId(poll_op.task_id.context("Missing `task_id` field")?.id);
+        let metadata_id = MetaId(poll_op.metadata.context("Missing `metadata` field")?.id);
+
+        Ok(Self {
+            async_op_id,
+            resource_id,
+            task_id,
+            metadata_id,
+            name: poll_op.name,
+            is_ready: poll_op.is_ready,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(
+        new_tasks: Vec<console_api::tasks::Task>,
+        task_stats: Vec<(u64, console_api::tasks::Stats)>,
+        new_resources: Vec<console_api::resources::Resource>,
+        resource_stats: Vec<(u64, console_api::resources::Stats)>,
+    ) -> console_api::instrument::Update {
+        console_api::instrument::Update {
+            task_update: Some(console_api::tasks::TaskUpdate {
+                new_tasks,
+                stats_update: task_stats.into_iter().collect(),
+                dropped_events: 0,
+            }),
+            resource_update: Some(console_api::resources::ResourceUpdate {
+                new_resources,
+                stats_update: resource_stats.into_iter().collect(),
+                new_poll_ops: Vec::new(),
+                dropped_events: 0,
+            }),
+            async_op_update: Some(Default::default()),
+            ..Default::default()
+        }
+    }
+
+    fn location() -> console_api::Location {
+        console_api::Location {
+            file: Some("src/main.rs".to_owned()),
+            module_path: None,
+            line: Some(1),
+            column: Some(1),
+        }
+    }
+
+    fn task(id: u64) -> console_api::tasks::Task {
+        console_api::tasks::Task {
+            id: Some(console_api::Id { id }),
+            metadata: Some(console_api::MetaId { id: 1 }),
+            location: Some(location()),
+            ..Default::default()
+        }
+    }
+
+    fn task_stats(polls: u64, dropped_at: Option<SystemTime>) -> console_api::tasks::Stats {
+        console_api::tasks::Stats {
+            created_at: Some(SystemTime::now().into()),
+            dropped_at: dropped_at.map(Into::into),
+            poll_stats: Some(console_api::PollStats {
+                polls,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn resource(id: u64) -> console_api::resources::Resource {
+        console_api::resources::Resource {
+            id: Some(console_api::Id { id }),
+            metadata: Some(console_api::MetaId { id: 1 }),
+            kind: Some(console_api::resources::resource::Kind {
+                kind: Some(console_api::resources::resource::kind::Kind::Other(
+                    "Sleep".to_owned(),
+                )),
+            }),
+            location: Some(location()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stats_updates_are_applied_to_known_tasks() {
+        let mut state = ConsoleState::default();
+
+        state
+            .apply_update(
+                update(
+                    vec![task(1), task(2)],
+                    vec![(1, task_stats(1, None)), (2, task_stats(1, None))],
+                    Vec::new(),
+                    Vec::new(),
+                ),
+                &Retention::default(),
+            )
+            .unwrap();
+        assert_eq!(state.tasks[&TaskId(1)].stats.as_ref().unwrap().polls, 1);
+
+        state
+            .apply_update(
+                update(
+                    Vec::new(),
+                    vec![(1, task_stats(5, None))],
+                    Vec::new(),
+                    Vec::new(),
+                ),
+                &Retention::default(),
+            )
+            .unwrap();
+        assert_eq!(state.tasks[&TaskId(1)].stats.as_ref().unwrap().polls, 5);
+        assert_eq!(state.tasks[&TaskId(2)].stats.as_ref().unwrap().polls, 1);
+
+        state
+            .apply_update(
+                update(
+                    Vec::new(),
+                    vec![(2, task_stats(2, Some(SystemTime::now())))],
+                    Vec::new(),
+                    Vec::new(),
+                ),
+                &Retention::default(),
+            )
+            .unwrap();
+        assert!(!state.tasks[&TaskId(1)].is_completed());
+        assert!(state.tasks[&TaskId(2)].is_completed());
+    }
+
+    #[test]
+    fn stats_updates_are_applied_to_known_resources() {
+        let mut state = ConsoleState::default();
+
+        state
+            .apply_update(
+                update(Vec::new(), Vec::new(), vec![resource(1)], Vec::new()),
+                &Retention::default(),
+            )
+            .unwrap();
+        assert!(state.resources[&ResourceId(1)].stats.is_none());
+
+        let dropped_at = SystemTime::now();
+        state
+            .apply_update(
+                update(
+                    Vec::new(),
+                    Vec::new(),
+                    Vec::new(),
+                    vec![(
+                        1,
+                        console_api::resources::Stats {
+                            dropped_at: Some(dropped_at.into()),
+                            ..Default::default()
+                        },
+                    )],
+                ),
+                &Retention::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            state.resources[&ResourceId(1)]
+                .stats
+                .as_ref()
+                .unwrap()
+                .dropped_at,
+            Some(dropped_at)
+        );
+    }
+
+    #[test]
+    fn parse_retention() {
+        assert_eq!("forever".parse::<Retention>().unwrap(), Retention::Forever);
+        assert_eq!("100".parse::<Retention>().unwrap(), Retention::Count(100));
+        assert_eq!(
+            "500ms".parse::<Retention>().unwrap(),
+            Retention::Duration(Duration::from_millis(500))
+        );
+        assert_eq!(
+            "2h".parse::<Retention>().unwrap(),
+            Retention::Duration(Duration::from_secs(2 * 60 * 60))
+        );
+
+        for invalid in [
+            "",
+            "s",
+            "5d",
+            "5 m",
+            "-5s",
+            "18446744073709551615m",
+            "5124095576030432h",
+        ] {
+            assert!(invalid.parse::<Retention>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn closed_subscriptions_are_removed_and_reopened() {
+        let subscriptions = ConsoleSubscriptions::default();
+        // nothing listens on this port so the subscription keeps retrying
+        let addr = ConsoleAddr::Tcp {
+            host: "127.0.0.1".to_owned(),
+            port: 1,
+        };
+
+        let mut watch = subscriptions.subscribe(addr.clone()).await.unwrap();
+        assert!(watch.connected().await.is_err());
+        assert!(subscriptions.inner.lock().await.contains_key(&addr));
+
+        // dropping the last watch makes the subscription remove itself
+        drop(watch);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while subscriptions.inner.lock().await.contains_key(&addr) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("subscription was never removed");
+
+        // so the next visitor gets a new subscription rather than the dead one
+        let mut watch = subscriptions.subscribe(addr.clone()).await.unwrap();
+        assert_eq!(watch.borrow().connection, ConnectionStatus::Connecting);
+        assert!(watch.connected().await.is_err());
+        assert!(subscriptions.inner.lock().await.contains_key(&addr));
+    }
+
+    #[tokio::test]
+    async fn subscriptions_keep_their_tls_options() {
+        let subscriptions = ConsoleSubscriptions::default();
+        let addr = ConsoleAddr::Tcp {
+            host: "127.0.0.1".to_owned(),
+            port: 1,
+        };
+        let tls = TlsOptions {
+            domain: Some("localhost".to_owned()),
+            ..Default::default()
+        };
+
+        let _watch = subscriptions.subscribe(addr.clone()).await.unwrap();
+        assert!(subscriptions
+            .subscribe_with_tls(addr.clone(), Some(tls))
+            .await
+            .is_err());
+        assert!(subscriptions
+            .subscribe_with_tls(addr.clone(), None)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn recordings_end_with_their_subscription() {
+        let dir = std::env::temp_dir().join(format!("recording-test-{}", uuid::Uuid::new_v4()));
+        let recorder = Recorder::new(dir.clone());
+        let subscriptions = ConsoleSubscriptions::default().record_all(recorder.clone());
+        let addr = ConsoleAddr::Tcp {
+            host: "127.0.0.1".to_owned(),
+            port: 1,
+        };
+
+        let mut watch = subscriptions.subscribe(addr.clone()).await.unwrap();
+        assert!(recorder.is_recording(&addr));
+        assert!(watch.connected().await.is_err());
+
+        // the recording doesn't count as someone watching
+        drop(watch);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while subscriptions.inner.lock().await.contains_key(&addr)
+                || recorder.is_recording(&addr)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("recording kept the subscription open");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}